@@ -0,0 +1,118 @@
+//! Atomic multi-key write batches
+//!
+//! A [`WriteBatch`] buffers an ordered list of put/delete operations so they
+//! can be committed together: [`DB::write`](crate::DB::write) serializes the
+//! whole batch into a single WAL record and applies every operation to the
+//! `MemTable` in order, so a crash recovers either all of the batch or none
+//! of it.
+
+use crate::error::{Error, Result};
+use crate::storage::BatchOp;
+
+/// Default maximum number of operations a [`WriteBatch`] will accept before
+/// returning [`Error::WriteBatchFull`].
+const DEFAULT_MAX_BATCH_OPS: usize = 10_000;
+
+/// An ordered, atomically-applied group of put/delete operations
+///
+/// # Examples
+/// ```
+/// use rocksdb_clone::batch::WriteBatch;
+///
+/// let mut batch = WriteBatch::new();
+/// batch.put(b"key1", b"value1").unwrap();
+/// batch.delete(b"key2").unwrap();
+/// assert_eq!(batch.len(), 2);
+/// ```
+#[derive(Debug, Default)]
+pub struct WriteBatch {
+    ops: Vec<BatchOp>,
+    max_capacity: usize,
+}
+
+impl WriteBatch {
+    /// Creates a new, empty batch with the default maximum capacity
+    pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_MAX_BATCH_OPS)
+    }
+
+    /// Creates a new, empty batch that rejects more than `max_capacity` ops
+    pub fn with_capacity(max_capacity: usize) -> Self {
+        Self {
+            ops: Vec::new(),
+            max_capacity,
+        }
+    }
+
+    /// Buffers a put operation
+    ///
+    /// # Errors
+    /// Returns [`Error::WriteBatchFull`] if the batch is already at its
+    /// configured maximum capacity.
+    pub fn put(&mut self, key: impl Into<Vec<u8>>, value: impl Into<Vec<u8>>) -> Result<()> {
+        self.push(BatchOp::Put {
+            key: key.into(),
+            value: value.into(),
+        })
+    }
+
+    /// Buffers a delete operation
+    ///
+    /// # Errors
+    /// Returns [`Error::WriteBatchFull`] if the batch is already at its
+    /// configured maximum capacity.
+    pub fn delete(&mut self, key: impl Into<Vec<u8>>) -> Result<()> {
+        self.push(BatchOp::Delete { key: key.into() })
+    }
+
+    fn push(&mut self, op: BatchOp) -> Result<()> {
+        if self.ops.len() >= self.max_capacity {
+            return Err(Error::WriteBatchFull(self.max_capacity));
+        }
+        self.ops.push(op);
+        Ok(())
+    }
+
+    /// Returns the number of operations buffered in this batch
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Returns `true` if the batch has no buffered operations
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Returns the buffered operations in commit order
+    ///
+    /// Sequence numbers aren't assigned yet: the store mints one per op only once
+    /// the batch is actually committed (see [`crate::storage::PersistentStore`]).
+    pub(crate) fn ops(&self) -> &[BatchOp] {
+        &self.ops
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_batch_buffers_ops_in_order() {
+        let mut batch = WriteBatch::new();
+        batch.put(b"a".to_vec(), b"1".to_vec()).unwrap();
+        batch.delete(b"b".to_vec()).unwrap();
+
+        assert_eq!(batch.len(), 2);
+        assert!(matches!(batch.ops()[0], BatchOp::Put { .. }));
+        assert!(matches!(batch.ops()[1], BatchOp::Delete { .. }));
+    }
+
+    #[test]
+    fn test_write_batch_full() {
+        let mut batch = WriteBatch::with_capacity(1);
+        batch.put(b"a".to_vec(), b"1".to_vec()).unwrap();
+
+        let err = batch.put(b"b".to_vec(), b"2".to_vec()).unwrap_err();
+        assert!(matches!(err, Error::WriteBatchFull(1)));
+    }
+}
@@ -1,8 +1,28 @@
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use rocksdb_clone::config::Config;
-use rocksdb_clone::{error::Result, DB};
+use rocksdb_clone::storage::upgrade_data_dir;
+use rocksdb_clone::{error::Result, Backend, DB};
 use std::path::PathBuf;
 
+/// Storage backend selectable from the command line; mirrors
+/// [`rocksdb_clone::Backend`] with `clap`-friendly names
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum CliBackend {
+    /// Durable on-disk storage via a write-ahead log and SSTables
+    Disk,
+    /// Keeps the whole dataset in RAM; nothing survives past the process exiting
+    Memory,
+}
+
+impl From<CliBackend> for Backend {
+    fn from(backend: CliBackend) -> Self {
+        match backend {
+            CliBackend::Disk => Backend::Disk,
+            CliBackend::Memory => Backend::Memory,
+        }
+    }
+}
+
 /// A simple key-value store CLI
 #[derive(Debug, Parser)]
 #[clap(name = "rocksdb-clone", version = "0.1.0")]
@@ -11,6 +31,10 @@ struct Cli {
     #[clap(short, long, default_value = "rocksdb_data")]
     path: PathBuf,
 
+    /// Storage backend to use
+    #[clap(long, value_enum, default_value = "disk")]
+    backend: CliBackend,
+
     #[clap(subcommand)]
     command: Commands,
 }
@@ -25,6 +49,10 @@ enum Commands {
 
     /// Delete a key
     Delete { key: String },
+
+    /// Migrate the data directory's SSTables and WAL to the current on-disk
+    /// format version, backing up each rewritten file to `<name>.bak` first
+    Upgrade,
 }
 
 fn main() -> Result<()> {
@@ -34,7 +62,15 @@ fn main() -> Result<()> {
 
     let config = Config::new().path(cli.path);
 
-    let mut db = DB::open(&config.path, config.create_if_missing)?;
+    // `upgrade` rewrites files a normal `DB::open` would otherwise refuse to
+    // read, so it has to run before the store is opened rather than through it.
+    if let Commands::Upgrade = cli.command {
+        let migrated = upgrade_data_dir(&config.path)?;
+        println!("migrated {migrated} file(s) to the current format version");
+        return Ok(());
+    }
+
+    let mut db = DB::open_with_backend(&config.path, config.create_if_missing, cli.backend.into())?;
 
     match cli.command {
         Commands::Get { key } => {
@@ -52,6 +88,7 @@ fn main() -> Result<()> {
             db.delete(key.as_bytes())?;
             println!("OK");
         }
+        Commands::Upgrade => unreachable!("handled before the store is opened"),
     }
 
     Ok(())
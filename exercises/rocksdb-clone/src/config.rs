@@ -1,6 +1,42 @@
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
+/// Block compression codec applied to SSTable data sections
+///
+/// Every compressed block is tagged with its codec (see
+/// [`crate::storage::CompressorRegistry`]), so changing this setting only affects
+/// newly written SSTables; older files keep reading correctly under whatever codec
+/// they were written with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Compression {
+    /// Store data blocks uncompressed
+    #[default]
+    None,
+    /// Compress data blocks with Snappy (requires the `snappy` cargo feature)
+    Snappy,
+    /// Compress data blocks with LZ4 (requires the `lz4` cargo feature)
+    Lz4,
+    /// Compress data blocks with zlib (requires the `zlib` cargo feature)
+    Zlib,
+}
+
+/// AEAD cipher applied to SSTable data and index blocks for encryption-at-rest
+///
+/// Selected per table rather than globally in [`Config`], since encrypting a table
+/// also requires a passphrase the data key is derived from (see
+/// [`crate::storage::derive_key`]) -- not something this crate persists alongside
+/// its other settings. See [`crate::storage::SSTable::create_with_encryption`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum EncryptionType {
+    /// Store blocks unencrypted
+    #[default]
+    None,
+    /// Encrypt blocks with AES-256-GCM (requires the `encryption` cargo feature)
+    Aes256Gcm,
+    /// Encrypt blocks with ChaCha20-Poly1305 (requires the `encryption` cargo feature)
+    ChaCha20Poly1305,
+}
+
 /// Configuration for the RocksDB Clone
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -15,6 +51,30 @@ pub struct Config {
 
     /// Whether to create the database if it doesn't exist
     pub create_if_missing: bool,
+
+    /// Codec used to compress newly written SSTable data blocks
+    pub compression: Compression,
+
+    /// Number of level-0 SSTables that triggers a compaction of level 0
+    pub level0_file_trigger: usize,
+
+    /// How much bigger each level's byte budget is than the one above it
+    pub level_size_multiplier: u64,
+
+    /// Bits of Bloom filter budgeted per key in newly written SSTables
+    ///
+    /// `0` disables filters entirely (see [`crate::storage::NoFilterPolicy`]); ~10
+    /// gives roughly a 1% false-positive rate. To target a specific rate directly,
+    /// derive this from [`crate::storage::bits_per_key_for_false_positive_rate`].
+    pub bits_per_key: usize,
+
+    /// Whether to read SSTable files via memory mapping instead of buffered I/O
+    ///
+    /// Memory mapping turns a block read into a slice reference into the mapped
+    /// region, skipping the usual read syscall and buffer copy. Requires the crate's
+    /// `mmap` feature; without it this setting is silently ignored and SSTables
+    /// always fall back to buffered I/O.
+    pub use_mmap: bool,
 }
 
 impl Default for Config {
@@ -24,6 +84,11 @@ impl Default for Config {
             memtable_size: 64 * 1024 * 1024, // 64MB
             sync: false,
             create_if_missing: true,
+            compression: Compression::default(),
+            level0_file_trigger: 4,
+            level_size_multiplier: 10,
+            bits_per_key: 10,
+            use_mmap: false,
         }
     }
 }
@@ -57,4 +122,37 @@ impl Config {
         self.create_if_missing = create;
         self
     }
+
+    /// Set the codec used to compress newly written SSTable data blocks
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Set the number of level-0 SSTables that triggers a compaction of level 0
+    pub fn level0_file_trigger(mut self, level0_file_trigger: usize) -> Self {
+        self.level0_file_trigger = level0_file_trigger;
+        self
+    }
+
+    /// Set how much bigger each level's byte budget is than the one above it
+    pub fn level_size_multiplier(mut self, level_size_multiplier: u64) -> Self {
+        self.level_size_multiplier = level_size_multiplier;
+        self
+    }
+
+    /// Set the bits of Bloom filter budgeted per key; `0` disables filters entirely
+    ///
+    /// To target a specific false-positive rate instead, pass
+    /// [`crate::storage::bits_per_key_for_false_positive_rate`]'s result here.
+    pub fn bits_per_key(mut self, bits_per_key: usize) -> Self {
+        self.bits_per_key = bits_per_key;
+        self
+    }
+
+    /// Enable or disable reading SSTable files via memory mapping
+    pub fn use_mmap(mut self, use_mmap: bool) -> Self {
+        self.use_mmap = use_mmap;
+        self
+    }
 }
@@ -36,6 +36,36 @@ pub enum Error {
     #[error("Operation not supported: {0}")]
     NotSupported(String),
 
+    /// A `WriteBatch` exceeded its configured maximum number of operations
+    #[error("write batch exceeded maximum capacity of {0} operations")]
+    WriteBatchFull(usize),
+
+    /// A [`crate::txn::Transaction`] was aborted at commit time because a key in its
+    /// read set was overwritten by another write committed after the transaction's
+    /// snapshot was taken
+    #[error("transaction conflict: a key read by this transaction was modified after its snapshot")]
+    TransactionConflict,
+
+    /// A checksum stored alongside an on-disk block or section didn't match the
+    /// bytes actually read back, indicating bit rot or a torn write
+    #[error("corruption detected at offset {offset}: expected CRC {expected:#010x}, got {actual:#010x}")]
+    Corruption {
+        /// Byte offset of the corrupt block or section within its file
+        offset: u64,
+        /// CRC recorded alongside the bytes when they were written
+        expected: u32,
+        /// CRC actually computed over the bytes read back
+        actual: u32,
+    },
+
+    /// An encrypted block failed AEAD authentication on read, meaning either the
+    /// wrong key was used to open it or its ciphertext was tampered with
+    #[error("authentication failed for encrypted block at offset {offset}: wrong key or tampered data")]
+    AuthenticationFailed {
+        /// Byte offset of the block that failed authentication
+        offset: u64,
+    },
+
     /// Custom error
     #[error("Error: {0}")]
     Custom(String),
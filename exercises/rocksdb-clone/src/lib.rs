@@ -11,6 +11,12 @@
 #![warn(missing_docs)]
 #![warn(rustdoc::missing_crate_level_docs)]
 
+/// Atomic multi-key write batches
+///
+/// This module defines [`batch::WriteBatch`], which buffers an ordered list of
+/// put/delete operations so they can be committed to the database atomically.
+pub mod batch;
+
 /// Configuration types and utilities for the key-value store
 ///
 /// This module contains types and functions for managing the database configuration,
@@ -29,32 +35,162 @@ pub mod error;
 /// Write-Ahead Log (WAL), and SSTable components that make up the LSM tree storage engine.
 pub mod storage;
 
+/// Optimistic transactions with write-snapshot isolation
+///
+/// This module defines [`txn::Transaction`], which reads against a fixed [`Snapshot`]
+/// and buffers its writes like a [`batch::WriteBatch`], aborting at commit time if
+/// anything it read has since changed.
+pub mod txn;
+
+use batch::WriteBatch;
 use error::Result;
+use std::collections::BTreeMap;
+use std::ops::Bound;
 use std::path::Path;
-use storage::Store;
+use std::sync::{Arc, Mutex};
+use storage::{InMemoryStore, PersistentStore, Store};
+use txn::Transaction;
+
+/// Which storage engine a [`DB`] is backed by
+///
+/// Every backend implements [`storage::Store`], so [`DB`] only ever talks to it through
+/// that trait: switching backends (e.g. via the CLI's `--backend` flag) never changes
+/// how a [`DB`] is used, only its durability and performance characteristics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// Durable on-disk storage via a write-ahead log and SSTables (see
+    /// [`storage::PersistentStore`])
+    #[default]
+    Disk,
+    /// Keeps the whole dataset in RAM (see [`storage::InMemoryStore`]) with no WAL and
+    /// no files; nothing survives past the process exiting
+    Memory,
+}
+
+/// Tracks how many live [`Snapshot`]s are pinning each sequence number, so [`DB`] can
+/// tell compaction the oldest one it still has to keep versions around for
+///
+/// Shared (via `Arc`) between a [`DB`] and every [`Snapshot`] it has issued. A sequence
+/// number is pinned as long as at least one [`Snapshot`] (or any of its clones) holding
+/// it is still alive; [`SnapshotRegistry::min_live_seq`] is the lowest such sequence
+/// number, or `None` if nothing is pinned.
+#[derive(Debug, Clone, Default)]
+struct SnapshotRegistry {
+    pinned: Arc<Mutex<BTreeMap<u64, usize>>>,
+}
+
+impl SnapshotRegistry {
+    fn pin(&self, seq: u64) {
+        *self.pinned.lock().unwrap().entry(seq).or_insert(0) += 1;
+    }
+
+    fn unpin(&self, seq: u64) {
+        let mut pinned = self.pinned.lock().unwrap();
+        if let Some(count) = pinned.get_mut(&seq) {
+            *count -= 1;
+            if *count == 0 {
+                pinned.remove(&seq);
+            }
+        }
+    }
+
+    fn min_live_seq(&self) -> Option<u64> {
+        self.pinned.lock().unwrap().keys().next().copied()
+    }
+}
+
+/// Unpins a [`Snapshot`]'s sequence number from its [`SnapshotRegistry`] once every
+/// clone of the snapshot holding it has been dropped
+#[derive(Debug)]
+struct SnapshotPin {
+    seq: u64,
+    registry: SnapshotRegistry,
+}
+
+impl Drop for SnapshotPin {
+    fn drop(&mut self) {
+        self.registry.unpin(self.seq);
+    }
+}
+
+/// A point-in-time view of the database, captured by [`DB::snapshot`]
+///
+/// Reads taken against a snapshot (via [`DB::get_at`]) only see writes whose sequence
+/// number is less than or equal to the snapshot's, so they are unaffected by writes
+/// that happen after the snapshot was captured. While at least one clone of a
+/// `Snapshot` is alive, [`DB`] keeps every version of a key it could still see around
+/// for it, even across compaction -- see [`storage::Store::set_min_live_seq`].
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub(crate) seq: u64,
+    // `None` for a `Snapshot` that was never registered with a `DB` (e.g. constructed
+    // directly in a test); such a snapshot pins nothing.
+    pub(crate) pin: Option<Arc<SnapshotPin>>,
+}
+
+impl Snapshot {
+    /// Returns the sequence number captured by this snapshot
+    pub fn sequence(&self) -> u64 {
+        self.seq
+    }
+}
+
+impl PartialEq for Snapshot {
+    fn eq(&self, other: &Self) -> bool {
+        self.seq == other.seq
+    }
+}
+
+impl Eq for Snapshot {}
 
 /// Main database type that provides the key-value store interface
 pub struct DB {
-    /// Persistent storage backend
-    store: storage::PersistentStore,
+    /// Storage backend, selected by [`DB::open`]/[`DB::open_with_backend`]
+    store: Box<dyn Store>,
+    /// Sequence numbers pinned by every [`Snapshot`] this `DB` has issued that's still
+    /// alive, reported to `store` before any operation that might trigger compaction
+    snapshots: SnapshotRegistry,
 }
 
 impl DB {
-    /// Opens a database with the given configuration
+    /// Opens a disk-backed database with the given configuration
+    ///
+    /// Equivalent to [`DB::open_with_backend`] with [`Backend::Disk`].
     pub fn open<P: AsRef<Path>>(path: P, create_if_missing: bool) -> Result<Self> {
-        let path = path.as_ref();
-        if !path.exists() {
-            if create_if_missing {
-                std::fs::create_dir_all(path)?;
-            } else {
-                return Err(error::Error::DatabaseNotFound(
-                    path.to_string_lossy().to_string(),
-                ));
+        Self::open_with_backend(path, create_if_missing, Backend::Disk)
+    }
+
+    /// Opens a database using the given storage `backend`
+    ///
+    /// `path` and `create_if_missing` only apply to [`Backend::Disk`];
+    /// [`Backend::Memory`] ignores both and always starts out empty.
+    pub fn open_with_backend<P: AsRef<Path>>(
+        path: P,
+        create_if_missing: bool,
+        backend: Backend,
+    ) -> Result<Self> {
+        let store: Box<dyn Store> = match backend {
+            Backend::Disk => {
+                let path = path.as_ref();
+                if !path.exists() {
+                    if create_if_missing {
+                        std::fs::create_dir_all(path)?;
+                    } else {
+                        return Err(error::Error::DatabaseNotFound(
+                            path.to_string_lossy().to_string(),
+                        ));
+                    }
+                }
+
+                Box::new(PersistentStore::open(path)?)
             }
-        }
+            Backend::Memory => Box::new(InMemoryStore::new()),
+        };
 
-        let store = storage::PersistentStore::open(path)?;
-        Ok(Self { store })
+        Ok(Self {
+            store,
+            snapshots: SnapshotRegistry::default(),
+        })
     }
 
     /// Retrieves a value by key
@@ -64,18 +200,135 @@ impl DB {
 
     /// Inserts or updates a key-value pair
     pub fn put(&mut self, key: &[u8], value: Vec<u8>) -> Result<()> {
-        self.store.put(key, value)
+        let mut batch = WriteBatch::new();
+        batch.put(key, value)?;
+        self.write(batch)
     }
 
     /// Deletes a key from the database
     pub fn delete(&mut self, key: &[u8]) -> Result<()> {
-        self.store.delete(key)
+        let mut batch = WriteBatch::new();
+        batch.delete(key)?;
+        self.write(batch)
+    }
+
+    /// Captures a snapshot of the database at its current sequence number
+    ///
+    /// Pass the returned [`Snapshot`] to [`DB::get_at`] for a consistent, point-in-time
+    /// read that is unaffected by writes made after this call.
+    pub fn snapshot(&self) -> Snapshot {
+        let seq = self.store.current_seq();
+        self.snapshots.pin(seq);
+        Snapshot {
+            seq,
+            pin: Some(Arc::new(SnapshotPin {
+                seq,
+                registry: self.snapshots.clone(),
+            })),
+        }
+    }
+
+    /// Pushes the oldest sequence number any live [`Snapshot`] still needs visible down
+    /// into `store`, so it knows how far compaction has to keep a repeatedly-written
+    /// key's old versions around (see [`storage::Store::set_min_live_seq`])
+    fn sync_min_live_seq(&mut self) {
+        self.store.set_min_live_seq(self.snapshots.min_live_seq());
+    }
+
+    /// Retrieves the value for `key` as of `snapshot`, treating a tombstone as "not found"
+    pub fn get_at(&self, key: &[u8], snapshot: &Snapshot) -> Result<Option<Vec<u8>>> {
+        self.store.get_at(key, snapshot.seq)
+    }
+
+    /// Returns an ordered iterator over every key in `[start, end)`
+    ///
+    /// This is a k-way merge across the MemTable and all on-disk SSTables: when a key
+    /// exists in more than one source, only its newest version is yielded, and a
+    /// tombstone suppresses the key from the output entirely.
+    pub fn scan<'a>(
+        &'a self,
+        start: Bound<&'a [u8]>,
+        end: Bound<&'a [u8]>,
+    ) -> impl Iterator<Item = (Vec<u8>, Vec<u8>)> + 'a {
+        self.store.scan(start, end, None)
+    }
+
+    /// Like [`DB::scan`], but restricted to versions visible at `snapshot`
+    pub fn scan_at<'a>(
+        &'a self,
+        start: Bound<&'a [u8]>,
+        end: Bound<&'a [u8]>,
+        snapshot: &Snapshot,
+    ) -> impl Iterator<Item = (Vec<u8>, Vec<u8>)> + 'a {
+        self.store.scan(start, end, Some(snapshot.seq))
+    }
+
+    /// Atomically applies every put/delete buffered in `batch`
+    ///
+    /// On the disk backend, the batch is serialized into a single WAL record
+    /// before any of its operations are applied to the MemTable, so a crash
+    /// recovers either all of the batch or none of it.
+    pub fn write(&mut self, batch: WriteBatch) -> Result<()> {
+        self.sync_min_live_seq();
+        self.store.write_batch(batch.ops())
     }
 
     /// Flushes any pending writes to disk
     pub fn flush(&mut self) -> Result<()> {
+        self.sync_min_live_seq();
         self.store.flush()
     }
+
+    /// Manually triggers compaction of every SSTable that could overlap `[start, end)`
+    ///
+    /// Compaction otherwise only runs automatically as levels grow past their
+    /// configured triggers (see [`crate::config::Config::level0_file_trigger`]);
+    /// this is an escape hatch for forcing it, e.g. after a large batch of deletes
+    /// to reclaim space held by tombstones sooner.
+    ///
+    /// A no-op if no SSTable's key range could possibly hold a key in
+    /// `[start, end)` (see [`storage::PersistentStore::compact_range`]); otherwise
+    /// the whole dataset is compacted, since compaction doesn't yet support
+    /// merging just the tables a range overlaps. A no-op either way on backends
+    /// with nothing on disk to merge, like [`Backend::Memory`].
+    pub fn compact_range(&mut self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Result<()> {
+        self.sync_min_live_seq();
+        self.store.compact_range(start, end)
+    }
+
+    /// Manually triggers compaction of every level, the same as
+    /// [`DB::compact_range`] over the whole key space
+    pub fn compact(&mut self) -> Result<()> {
+        self.compact_range(Bound::Unbounded, Bound::Unbounded)
+    }
+
+    /// Begins a new optimistic [`Transaction`], reading against a snapshot of the
+    /// database taken right now
+    ///
+    /// See [`DB::commit_transaction`] for how it's validated and applied.
+    pub fn begin_transaction(&self) -> Transaction {
+        Transaction::new(self.snapshot())
+    }
+
+    /// Validates and applies `txn`, the write-snapshot-isolation counterpart to
+    /// [`DB::write`]
+    ///
+    /// Aborts with [`error::Error::TransactionConflict`] without applying any of
+    /// `txn`'s writes if a key it read (via [`Transaction::get`]) has been written
+    /// again since `txn`'s snapshot was taken. This compares sequence numbers, not
+    /// values -- see [`crate::txn`] for why a key written back to the value `txn` saw
+    /// is still a conflict. Otherwise, `txn`'s buffered writes are applied atomically,
+    /// exactly like [`DB::write`].
+    pub fn commit_transaction(&mut self, txn: Transaction) -> Result<()> {
+        let snapshot_seq = txn.snapshot().seq;
+        for key in txn.reads() {
+            if self.store.latest_seq(key)?.is_some_and(|seq| seq > snapshot_seq) {
+                return Err(error::Error::TransactionConflict);
+            }
+        }
+
+        self.write(txn.into_writes())
+    }
 }
 
 #[cfg(test)]
@@ -123,4 +376,190 @@ mod tests {
 
         Ok(())
     }
+
+    /// Tests that `DB::compact()` merges the on-disk data down without losing or
+    /// resurrecting anything: a deleted key stays deleted, a live key survives
+    #[test]
+    fn test_db_compact_preserves_visible_state() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let mut db = DB::open(temp_dir.path(), true)?;
+
+        db.put(b"a", b"1".to_vec())?;
+        db.put(b"b", b"2".to_vec())?;
+        db.delete(b"a")?;
+        db.flush()?;
+
+        db.compact()?;
+
+        assert_eq!(db.get(b"a")?, None);
+        assert_eq!(db.get(b"b")?, Some(b"2".to_vec()));
+
+        Ok(())
+    }
+
+    /// Tests that `DB::compact()` terminates and keeps data correct once the
+    /// dataset already spans more than one level: a first `compact()` pushes
+    /// data from level 0 into level 1, so the second `compact()` below has to
+    /// fold a non-empty level 1 down as well, not just a flat level-0 table.
+    #[test]
+    fn test_db_compact_terminates_with_multiple_levels() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let mut db = DB::open(temp_dir.path(), true)?;
+
+        db.put(b"a", b"1".to_vec())?;
+        db.flush()?;
+        db.put(b"b", b"2".to_vec())?;
+        db.flush()?;
+        db.compact()?;
+
+        db.put(b"c", b"3".to_vec())?;
+        db.flush()?;
+        db.delete(b"a")?;
+        db.flush()?;
+        db.compact()?;
+
+        assert_eq!(db.get(b"a")?, None);
+        assert_eq!(db.get(b"b")?, Some(b"2".to_vec()));
+        assert_eq!(db.get(b"c")?, Some(b"3".to_vec()));
+
+        Ok(())
+    }
+
+    /// Tests that `get`/`get_at` fall through a miss in the newest SSTable to
+    /// check older ones, rather than stopping at the first one searched
+    #[test]
+    fn test_db_get_falls_through_to_older_sstable() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let mut db = DB::open(temp_dir.path(), true)?;
+
+        // Flushed separately so each key lives in its own SSTable, newest last.
+        db.put(b"older", b"1".to_vec())?;
+        db.flush()?;
+        let snapshot = db.snapshot();
+
+        db.put(b"newer", b"2".to_vec())?;
+        db.flush()?;
+
+        assert_eq!(db.get(b"older")?, Some(b"1".to_vec()));
+        assert_eq!(db.get(b"newer")?, Some(b"2".to_vec()));
+        assert_eq!(db.get_at(b"older", &snapshot)?, Some(b"1".to_vec()));
+
+        Ok(())
+    }
+
+    /// Tests that a key written more than one data block's worth of restart
+    /// intervals' worth of times within a single flush still reads back its
+    /// newest version, not a stale one
+    ///
+    /// A flushed data block's restart points are assumed distinct so a binary
+    /// search over them can find where to start scanning for a key -- but more
+    /// than 16 versions of the same key in one block (the block's restart
+    /// interval) means every restart point in that run shares the same key,
+    /// which used to send the search to the wrong (oldest) end of the run.
+    #[test]
+    fn test_db_get_after_many_writes_to_one_key_before_flush() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let mut db = DB::open(temp_dir.path(), true)?;
+
+        for i in 0..20 {
+            db.put(b"hot", format!("value-{i}").into_bytes())?;
+        }
+        db.flush()?;
+
+        assert_eq!(db.get(b"hot")?, Some(b"value-19".to_vec()));
+
+        Ok(())
+    }
+
+    /// Runs the same put/delete/snapshot assertions against every [`Backend`], so the
+    /// two [`storage::Store`] implementations can't silently drift apart in behavior
+    #[test]
+    fn test_every_backend_behaves_the_same() -> Result<()> {
+        let temp_dir = tempdir()?;
+
+        for backend in [Backend::Disk, Backend::Memory] {
+            let mut db = DB::open_with_backend(temp_dir.path().join("backend"), true, backend)?;
+
+            assert_eq!(db.get(b"key")?, None);
+
+            db.put(b"key", b"value1".to_vec())?;
+            let snapshot = db.snapshot();
+            db.put(b"key", b"value2".to_vec())?;
+
+            assert_eq!(db.get(b"key")?, Some(b"value2".to_vec()));
+            assert_eq!(db.get_at(b"key", &snapshot)?, Some(b"value1".to_vec()));
+
+            db.delete(b"key")?;
+            assert_eq!(db.get(b"key")?, None);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_transaction_commits_when_read_set_is_unchanged() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let mut db = DB::open(temp_dir.path(), true)?;
+
+        db.put(b"balance", b"100".to_vec())?;
+
+        let mut txn = db.begin_transaction();
+        assert_eq!(txn.get(&db, b"balance")?, Some(b"100".to_vec()));
+        txn.put(b"balance".to_vec(), b"90".to_vec())?;
+
+        db.commit_transaction(txn)?;
+
+        assert_eq!(db.get(b"balance")?, Some(b"90".to_vec()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_transaction_aborts_on_conflicting_write() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let mut db = DB::open(temp_dir.path(), true)?;
+
+        db.put(b"balance", b"100".to_vec())?;
+
+        let mut txn = db.begin_transaction();
+        assert_eq!(txn.get(&db, b"balance")?, Some(b"100".to_vec()));
+        txn.put(b"balance".to_vec(), b"90".to_vec())?;
+
+        // A write lands against `balance` after the transaction's snapshot but
+        // before it commits.
+        db.put(b"balance", b"50".to_vec())?;
+
+        let err = db.commit_transaction(txn).unwrap_err();
+        assert!(matches!(err, error::Error::TransactionConflict));
+
+        // The losing transaction's write never applied.
+        assert_eq!(db.get(b"balance")?, Some(b"50".to_vec()));
+
+        Ok(())
+    }
+
+    /// Tests that the conflict check catches an intervening write even when it
+    /// writes the same value right back (the ABA problem), since comparing
+    /// sequence numbers rather than values can't be fooled by that
+    #[test]
+    fn test_transaction_aborts_on_aba_conflicting_write() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let mut db = DB::open(temp_dir.path(), true)?;
+
+        db.put(b"balance", b"100".to_vec())?;
+
+        let mut txn = db.begin_transaction();
+        assert_eq!(txn.get(&db, b"balance")?, Some(b"100".to_vec()));
+        txn.put(b"balance".to_vec(), b"90".to_vec())?;
+
+        // A write lands against `balance` after the transaction's snapshot, then
+        // another puts it right back to the value the transaction originally saw.
+        db.put(b"balance", b"50".to_vec())?;
+        db.put(b"balance", b"100".to_vec())?;
+
+        let err = db.commit_transaction(txn).unwrap_err();
+        assert!(matches!(err, error::Error::TransactionConflict));
+
+        Ok(())
+    }
 }
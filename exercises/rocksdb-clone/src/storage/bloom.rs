@@ -0,0 +1,216 @@
+//! Bloom filter blocks for skipping SSTables that can't contain a key
+//!
+//! A filter is built once per SSTable, from every key it holds, and stored in its
+//! own on-disk section (see [`crate::storage::sstable`]) so it's loaded once when
+//! the table is opened rather than rebuilt on every lookup. [`SSTable::get`] consults
+//! it first and skips the table outright on a negative, without touching the index
+//! or data sections, at the cost of a configurable false-positive rate (see
+//! [`crate::config::Config::bits_per_key`], or
+//! [`bits_per_key_for_false_positive_rate`] to derive it from a target rate
+//! directly). Setting `bits_per_key` to `0` disables the filter entirely, for
+//! write-heavy tables where building one isn't worth the cost.
+//!
+//! [`SSTable::get`]: crate::storage::SSTable::get
+
+use std::sync::Arc;
+
+/// One 32-bit FNV-1a hash of `key`, seeded so two independent hashes can be
+/// derived from it with different `seed` values
+fn hash32(key: &[u8], seed: u32) -> u32 {
+    let mut hash = 0x811c9dc5u32 ^ seed;
+    for &b in key {
+        hash ^= b as u32;
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    hash
+}
+
+/// Policy for building and querying a per-SSTable filter block
+///
+/// Lets the filter implementation (or the choice of having none at all, via
+/// [`NoFilterPolicy`]) vary independently of the SSTable format.
+pub trait FilterPolicy: Send + Sync {
+    /// Builds a filter block covering every key in `keys`
+    fn build(&self, keys: &[&[u8]]) -> Vec<u8>;
+
+    /// Returns `false` only if `key` is definitely absent from the table `filter`
+    /// was built for; a `true` result may be a false positive
+    fn may_contain(&self, filter: &[u8], key: &[u8]) -> bool;
+}
+
+/// Bloom filter policy with a configurable bits-per-key budget
+///
+/// Derives `k = round(bits_per_key * ln 2)` hash functions from two 32-bit hashes
+/// of the key via double hashing (`g_i(key) = h1(key) + i * h2(key)`), the standard
+/// Kirsch-Mitzenmacher construction. `k` is stored alongside the bit array, so a
+/// reader doesn't need to know `bits_per_key` to query a filter someone else built.
+pub struct BloomFilterPolicy {
+    bits_per_key: usize,
+}
+
+impl BloomFilterPolicy {
+    /// Creates a policy that budgets `bits_per_key` bits of filter per key
+    ///
+    /// ~10 bits per key gives roughly a 1% false-positive rate.
+    pub fn new(bits_per_key: usize) -> Self {
+        Self { bits_per_key }
+    }
+
+    fn num_hashes(bits_per_key: usize) -> u8 {
+        let k = (bits_per_key as f64 * std::f64::consts::LN_2).round() as i64;
+        k.clamp(1, 30) as u8
+    }
+}
+
+/// Translates a target false-positive rate into an equivalent bits-per-key budget,
+/// via `bits_per_key = ceil(-ln(target_fpr) / (ln 2)^2)`
+///
+/// Since [`BloomFilterPolicy`] budgets a fixed number of bits per key rather than a
+/// fixed total filter size, the resulting policy targets `target_fpr` regardless of
+/// how many keys a given table ends up holding, not just the entry count it was
+/// sized for.
+///
+/// # Example
+/// ```
+/// use rocksdb_clone::storage::bits_per_key_for_false_positive_rate;
+///
+/// // ~10 bits per key, matching the crate's default false-positive rate of ~1%.
+/// assert_eq!(bits_per_key_for_false_positive_rate(0.01), 10);
+/// ```
+pub fn bits_per_key_for_false_positive_rate(target_fpr: f64) -> usize {
+    let bits_per_key = -target_fpr.ln() / std::f64::consts::LN_2.powi(2);
+    bits_per_key.ceil().max(1.0) as usize
+}
+
+impl FilterPolicy for BloomFilterPolicy {
+    fn build(&self, keys: &[&[u8]]) -> Vec<u8> {
+        if keys.is_empty() || self.bits_per_key == 0 {
+            return Vec::new();
+        }
+
+        let num_bytes = (keys.len() * self.bits_per_key).div_ceil(8).max(8);
+        let num_bits = num_bytes * 8;
+        let k = Self::num_hashes(self.bits_per_key);
+
+        let mut bits = vec![0u8; num_bytes];
+        for key in keys {
+            let h1 = hash32(key, 0);
+            let h2 = hash32(key, 0x9e37_79b9);
+            for i in 0..u32::from(k) {
+                let bit = (h1.wrapping_add(i.wrapping_mul(h2)) as usize) % num_bits;
+                bits[bit / 8] |= 1 << (bit % 8);
+            }
+        }
+
+        // Stored as `[k (1 byte)][bit array]` so `may_contain` is self-contained.
+        let mut filter = Vec::with_capacity(bits.len() + 1);
+        filter.push(k);
+        filter.extend_from_slice(&bits);
+        filter
+    }
+
+    fn may_contain(&self, filter: &[u8], key: &[u8]) -> bool {
+        let Some((&k, bits)) = filter.split_first() else {
+            // No filter block was built for this table (filters were disabled, or
+            // it held no keys) — defer to the index instead of ruling `key` out.
+            return true;
+        };
+
+        let num_bits = bits.len() * 8;
+        let h1 = hash32(key, 0);
+        let h2 = hash32(key, 0x9e37_79b9);
+        (0..u32::from(k)).all(|i| {
+            let bit = (h1.wrapping_add(i.wrapping_mul(h2)) as usize) % num_bits;
+            bits[bit / 8] & (1 << (bit % 8)) != 0
+        })
+    }
+}
+
+/// A null filter policy that never rules out a key, for when filters are disabled
+pub struct NoFilterPolicy;
+
+impl FilterPolicy for NoFilterPolicy {
+    fn build(&self, _keys: &[&[u8]]) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn may_contain(&self, _filter: &[u8], _key: &[u8]) -> bool {
+        true
+    }
+}
+
+/// Returns the filter policy configured by `bits_per_key`: a real Bloom filter
+/// when positive, or [`NoFilterPolicy`] when it's `0` (filters disabled)
+pub fn policy_for(bits_per_key: usize) -> Arc<dyn FilterPolicy> {
+    if bits_per_key == 0 {
+        Arc::new(NoFilterPolicy)
+    } else {
+        Arc::new(BloomFilterPolicy::new(bits_per_key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bloom_filter_never_rejects_a_present_key() {
+        let policy = BloomFilterPolicy::new(10);
+        let keys: Vec<Vec<u8>> = (0..500).map(|i| format!("key_{i}").into_bytes()).collect();
+        let key_refs: Vec<&[u8]> = keys.iter().map(|k| k.as_slice()).collect();
+
+        let filter = policy.build(&key_refs);
+
+        for key in &key_refs {
+            assert!(policy.may_contain(&filter, key));
+        }
+    }
+
+    #[test]
+    fn test_bloom_filter_false_positive_rate_is_reasonable() {
+        let policy = BloomFilterPolicy::new(10);
+        let keys: Vec<Vec<u8>> = (0..1000).map(|i| format!("present_{i}").into_bytes()).collect();
+        let key_refs: Vec<&[u8]> = keys.iter().map(|k| k.as_slice()).collect();
+
+        let filter = policy.build(&key_refs);
+
+        let false_positives = (0..1000)
+            .map(|i| format!("absent_{i}").into_bytes())
+            .filter(|key| policy.may_contain(&filter, key))
+            .count();
+
+        // ~10 bits/key targets ~1%; allow generous slack since this is a tiny sample.
+        assert!(
+            false_positives < 50,
+            "expected well under 5% false positives, got {false_positives}/1000"
+        );
+    }
+
+    #[test]
+    fn test_empty_filter_defers_to_index() {
+        let policy = BloomFilterPolicy::new(10);
+        let filter = policy.build(&[]);
+        assert!(filter.is_empty());
+        assert!(policy.may_contain(&filter, b"anything"));
+    }
+
+    #[test]
+    fn test_no_filter_policy_always_may_contain() {
+        let policy = NoFilterPolicy;
+        assert!(policy.build(&[b"a"]).is_empty());
+        assert!(policy.may_contain(&[], b"anything"));
+    }
+
+    #[test]
+    fn test_bits_per_key_for_false_positive_rate_matches_default() {
+        // The crate's ~1% default false-positive rate corresponds to ~10 bits/key.
+        assert_eq!(bits_per_key_for_false_positive_rate(0.01), 10);
+    }
+
+    #[test]
+    fn test_bits_per_key_for_false_positive_rate_grows_as_rate_shrinks() {
+        let loose = bits_per_key_for_false_positive_rate(0.1);
+        let tight = bits_per_key_for_false_positive_rate(0.001);
+        assert!(tight > loose);
+    }
+}
@@ -0,0 +1,312 @@
+//! Size-tiered compaction across the leveled SSTable set
+//!
+//! Level 0 holds every SSTable flushed straight from the MemTable; because flushes
+//! happen independently, its tables may overlap in key range and must be searched
+//! newest-first. Once level 0 accumulates [`CompactionConfig::level0_file_trigger`]
+//! files (or a deeper level grows past its own byte budget), its tables are merged
+//! with [`VersionMergeIterator`] into a single new table one level down, keeping
+//! each key's newest version plus any older version a live snapshot still needs
+//! (see [`compact_tables`]).
+
+use crate::config::Compression;
+use crate::error::Result;
+use crate::storage::merge::VersionMergeIterator;
+use crate::storage::sstable::{SSTable, SSTableBuilder};
+use std::path::Path;
+
+/// Tuning knobs for when a level is compacted into the next
+#[derive(Debug, Clone, Copy)]
+pub struct CompactionConfig {
+    /// Number of level-0 SSTables that triggers a compaction of level 0
+    pub level0_file_trigger: usize,
+    /// How much bigger each level's byte budget is than the one above it
+    pub level_size_multiplier: u64,
+}
+
+impl Default for CompactionConfig {
+    fn default() -> Self {
+        Self {
+            level0_file_trigger: 4,
+            level_size_multiplier: 10,
+        }
+    }
+}
+
+impl CompactionConfig {
+    /// Returns the byte budget for `level`, scaled up from level 0's budget
+    pub fn level_byte_budget(&self, level: usize, level0_byte_budget: u64) -> u64 {
+        level0_byte_budget.saturating_mul(self.level_size_multiplier.saturating_pow(level as u32))
+    }
+}
+
+/// Merges `inputs` into a single new SSTable at `output_path`, keeping only the
+/// versions of each key something might still need
+///
+/// Input order doesn't matter (see [`VersionMergeIterator`]): every version any
+/// input table held is considered in the same newest-to-oldest order it's stored
+/// in. For each key, this always keeps the newest version (for ordinary reads),
+/// plus -- if `min_live_seq` is `Some` -- the newest version at or before that
+/// floor (the version the oldest live [`crate::Snapshot`](crate::Snapshot) or
+/// [`crate::txn::Transaction`] still needs); every other, strictly older version
+/// is dropped, since no live snapshot's floor is old enough to require it.
+/// `min_live_seq` of `None` means no snapshot is pinned, so a key collapses down
+/// to just its newest version -- this is what actually bounds the space and read
+/// amplification a repeatedly-overwritten key would otherwise accumulate forever.
+///
+/// The one exception: if a key's newest version turns out to be its *only*
+/// surviving version and it's a tombstone, it's physically dropped instead of
+/// kept when `drop_tombstones` is set; callers should only set it when no deeper
+/// level can still hold an even older version of that key, since omitting the
+/// tombstone while one does would resurrect it.
+pub fn compact_tables(
+    inputs: &[SSTable],
+    output_path: impl AsRef<Path>,
+    drop_tombstones: bool,
+    min_live_seq: Option<u64>,
+    compression: Compression,
+    bits_per_key: usize,
+    use_mmap: bool,
+) -> Result<SSTable> {
+    let sources = inputs
+        .iter()
+        .map(|table| table.iter_all_versions().map(|entries| Box::new(entries.into_iter()) as _))
+        .collect::<Result<Vec<_>>>()?;
+
+    // Stream the merged output straight into the new table instead of buffering it
+    // in a `Vec` first -- a compaction's merged output can be as large as every
+    // input table combined, so materializing it up front would double peak memory.
+    let mut builder = SSTableBuilder::new(output_path, compression, bits_per_key, use_mmap)?;
+    let mut merger = VersionMergeIterator::new(sources).peekable();
+    while let Some((key, seq, value)) = merger.next() {
+        // Collect every version of `key` still worth keeping: the newest is always
+        // needed, and (when `min_live_seq` is `Some`) versions are pulled one at a
+        // time until one at or before `min_live_seq` is found (that's the version
+        // the oldest live snapshot needs) or the key's versions run out. Anything
+        // older than that is visible to no live snapshot and is left where
+        // `merger` stands, to be drained below.
+        let mut kept = vec![(seq, value)];
+        let mut reached_floor = min_live_seq.map_or(true, |floor| seq <= floor);
+        while !reached_floor {
+            match merger.peek() {
+                Some((next_key, ..)) if *next_key == key => {
+                    let (seq, _, value) = merger.next().unwrap();
+                    reached_floor = min_live_seq.is_some_and(|floor| seq <= floor);
+                    kept.push((seq, value));
+                }
+                _ => break,
+            }
+        }
+        while merger.next_if(|(next_key, ..)| *next_key == key).is_some() {}
+
+        if let [(seq, None)] = kept.as_slice() {
+            if drop_tombstones {
+                continue;
+            }
+            builder.add_tombstone(&key, *seq)?;
+            continue;
+        }
+
+        for (seq, value) in kept {
+            match value {
+                Some(value) => builder.add(&key, seq, &value)?,
+                None => builder.add_tombstone(&key, seq)?,
+            }
+        }
+    }
+
+    builder.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Compression;
+
+    fn table(dir: &std::path::Path, name: &str, entries: &[(&[u8], u64, Option<&[u8]>)]) -> SSTable {
+        let mut sst = SSTable::create(dir.join(name)).unwrap();
+        let entries: Vec<_> = entries.iter().map(|(k, seq, v)| (k.to_vec(), *seq, v.map(|v| v.to_vec()))).collect();
+        sst.write_batch(&entries).unwrap();
+        sst
+    }
+
+    #[test]
+    fn test_compact_tables_prefers_newest_value_on_duplicate_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let oldest = table(dir.path(), "oldest.sst", &[(b"a", 1, Some(b"old")), (b"b", 2, Some(b"b"))]);
+        let newest = table(dir.path(), "newest.sst", &[(b"a", 3, Some(b"new"))]);
+
+        let output = compact_tables(
+            &[oldest, newest],
+            dir.path().join("out.sst"),
+            true,
+            None,
+            Compression::None,
+            10,
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(output.get(b"a").unwrap(), Some(b"new".to_vec()));
+        assert_eq!(output.get(b"b").unwrap(), Some(b"b".to_vec()));
+    }
+
+    #[test]
+    fn test_compact_tables_drop_tombstones_removes_deleted_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let oldest = table(dir.path(), "oldest.sst", &[(b"a", 1, Some(b"old"))]);
+        let newest = table(dir.path(), "newest.sst", &[(b"a", 2, None)]);
+
+        let output = compact_tables(
+            &[oldest, newest],
+            dir.path().join("dropped.sst"),
+            true,
+            None,
+            Compression::None,
+            10,
+            false,
+        )
+        .unwrap();
+        assert_eq!(output.get(b"a").unwrap(), None);
+
+        let dir2 = tempfile::tempdir().unwrap();
+        let oldest2 = table(dir2.path(), "oldest.sst", &[(b"a", 1, Some(b"old"))]);
+        let newest2 = table(dir2.path(), "newest.sst", &[(b"a", 2, None)]);
+
+        let kept = compact_tables(
+            &[oldest2, newest2],
+            dir2.path().join("kept.sst"),
+            false,
+            None,
+            Compression::None,
+            10,
+            false,
+        )
+        .unwrap();
+        assert_eq!(kept.get(b"a").unwrap(), None);
+    }
+
+    /// Tests that a key repeatedly overwritten with nothing pinning its history
+    /// (`min_live_seq: None`) collapses down to just its newest version -- this is
+    /// what actually bounds the space a hot key accumulates, rather than keeping
+    /// every version forever
+    #[test]
+    fn test_compact_tables_collapses_to_newest_when_nothing_pins_older_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let only = table(
+            dir.path(),
+            "only.sst",
+            &[(b"a", 5, Some(b"v5")), (b"a", 4, Some(b"v4")), (b"a", 3, Some(b"v3")), (b"a", 1, Some(b"v1"))],
+        );
+
+        let output =
+            compact_tables(&[only], dir.path().join("out.sst"), true, None, Compression::None, 10, false).unwrap();
+
+        assert_eq!(output.get(b"a").unwrap(), Some(b"v5".to_vec()));
+        // Every older version was physically dropped, not just shadowed: a read at
+        // an older sequence number that used to see an earlier version now finds
+        // nothing at all for the key.
+        assert_eq!(output.get_raw_at(b"a", 3).unwrap(), None);
+        assert_eq!(output.get_raw_at(b"a", 1).unwrap(), None);
+    }
+
+    /// Tests that compacting carries forward the version a live snapshot still
+    /// needs, even though it isn't the key's newest
+    #[test]
+    fn test_compact_tables_preserves_every_version_for_live_snapshots() {
+        let dir = tempfile::tempdir().unwrap();
+        let oldest = table(dir.path(), "oldest.sst", &[(b"a", 1, Some(b"old"))]);
+        let newest = table(dir.path(), "newest.sst", &[(b"a", 5, Some(b"new"))]);
+
+        // A live snapshot taken at seq 3 still needs to see "old" (the newest
+        // version at or before its own sequence number).
+        let output = compact_tables(
+            &[oldest, newest],
+            dir.path().join("out.sst"),
+            true,
+            Some(3),
+            Compression::None,
+            10,
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(output.get(b"a").unwrap(), Some(b"new".to_vec()));
+        assert_eq!(output.get_raw_at(b"a", 1).unwrap(), Some(Some(b"old".to_vec())));
+        assert_eq!(output.get_raw_at(b"a", 3).unwrap(), Some(Some(b"old".to_vec())));
+    }
+
+    /// Tests that a non-tombstone version pinned by `min_live_seq` is kept even
+    /// though it isn't the key's newest, while a version older still (and so
+    /// needed by no live snapshot) is dropped
+    #[test]
+    fn test_compact_tables_keeps_floor_pinned_value_but_drops_older_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let table1 = table(dir.path(), "t1.sst", &[(b"a", 1, Some(b"oldest"))]);
+        let table2 = table(dir.path(), "t2.sst", &[(b"a", 3, Some(b"middle"))]);
+        let table3 = table(dir.path(), "t3.sst", &[(b"a", 5, Some(b"newest"))]);
+
+        let output = compact_tables(
+            &[table1, table2, table3],
+            dir.path().join("out.sst"),
+            true,
+            Some(3),
+            Compression::None,
+            10,
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(output.get(b"a").unwrap(), Some(b"newest".to_vec()));
+        assert_eq!(output.get_raw_at(b"a", 3).unwrap(), Some(Some(b"middle".to_vec())));
+        assert_eq!(output.get_raw_at(b"a", 1).unwrap(), None);
+    }
+
+    /// Tests that a tombstone pinned by `min_live_seq` is kept rather than
+    /// dropped, even though it isn't the key's newest or oldest version, while a
+    /// version strictly older than the floor is dropped regardless
+    #[test]
+    fn test_compact_tables_keeps_floor_pinned_tombstone_even_when_dropping() {
+        let dir = tempfile::tempdir().unwrap();
+        let table1 = table(dir.path(), "t1.sst", &[(b"a", 1, Some(b"old"))]);
+        let table2 = table(dir.path(), "t2.sst", &[(b"a", 3, None)]);
+        let table3 = table(dir.path(), "t3.sst", &[(b"a", 5, Some(b"new"))]);
+
+        // A live snapshot at seq 3 or 4 needs to see the delete, not "old".
+        let output = compact_tables(
+            &[table1, table2, table3],
+            dir.path().join("out.sst"),
+            true,
+            Some(3),
+            Compression::None,
+            10,
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(output.get(b"a").unwrap(), Some(b"new".to_vec()));
+        assert_eq!(output.get_raw_at(b"a", 3).unwrap(), Some(None));
+        // Seq 1 is older than the floor, so it's needed by no live snapshot and
+        // was dropped along with it.
+        assert_eq!(output.get_raw_at(b"a", 1).unwrap(), None);
+    }
+
+    /// Tests that a key's *oldest* tombstone is physically dropped from the
+    /// output when `drop_tombstones` is set and nothing pins an older version, and
+    /// kept when `drop_tombstones` isn't set
+    #[test]
+    fn test_compact_tables_drops_oldest_tombstone_when_nothing_deeper_remains() {
+        let dir = tempfile::tempdir().unwrap();
+        let only = table(dir.path(), "only.sst", &[(b"a", 1, None)]);
+        let dropped =
+            compact_tables(&[only], dir.path().join("dropped.sst"), true, None, Compression::None, 10, false)
+                .unwrap();
+        assert_eq!(dropped.get_raw_at(b"a", 1).unwrap(), None);
+
+        let dir2 = tempfile::tempdir().unwrap();
+        let only2 = table(dir2.path(), "only2.sst", &[(b"a", 1, None)]);
+        let kept =
+            compact_tables(&[only2], dir2.path().join("kept.sst"), false, None, Compression::None, 10, false)
+                .unwrap();
+        assert_eq!(kept.get_raw_at(b"a", 1).unwrap(), Some(None));
+    }
+}
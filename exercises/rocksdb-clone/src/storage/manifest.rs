@@ -0,0 +1,60 @@
+//! Persists a [`PersistentStore`](crate::storage::PersistentStore)'s level
+//! layout to a small JSON file in its data directory
+//!
+//! Without this, [`PersistentStore::open`](crate::storage::PersistentStore::open)
+//! would have to rediscover which SSTable belongs to which level by scanning the
+//! data directory and parsing each file's name -- fragile the moment a crash
+//! leaves a half-written compaction output behind, since nothing on disk says
+//! whether that file was ever meant to be live. The manifest is the explicit
+//! record of what's live instead: it's rewritten, atomically, every time a flush
+//! or compaction changes the level layout.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Result;
+
+/// Filename of the manifest within a data directory
+const MANIFEST_FILE: &str = "MANIFEST";
+
+/// The level -> SSTable-id layout of a [`PersistentStore`](crate::storage::PersistentStore),
+/// plus the next id it should assign
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct Manifest {
+    pub(crate) next_sstable_id: u64,
+    /// `levels[level]` lists the ids of every SSTable at that level (see
+    /// `sstable_filename`), in the order [`PersistentStore`](crate::storage::PersistentStore)
+    /// considers them: append order for level 0 (oldest first, searched
+    /// newest-first), ascending key order for every deeper level.
+    pub(crate) levels: Vec<Vec<u64>>,
+}
+
+impl Manifest {
+    /// Reads the manifest from `data_dir`, or `None` if it doesn't exist --
+    /// e.g. a data directory created before this crate started writing one
+    pub(crate) fn load(data_dir: &Path) -> Result<Option<Self>> {
+        let path = Self::path(data_dir);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_slice(&fs::read(path)?)?))
+    }
+
+    /// Overwrites `data_dir`'s manifest with this layout
+    ///
+    /// Written to a temporary file first, then renamed into place, so a crash
+    /// mid-write can never leave a half-written manifest for the next `open` to
+    /// trip over.
+    pub(crate) fn save(&self, data_dir: &Path) -> Result<()> {
+        let tmp_path = data_dir.join(format!("{MANIFEST_FILE}.tmp"));
+        fs::write(&tmp_path, serde_json::to_vec(self)?)?;
+        fs::rename(&tmp_path, Self::path(data_dir))?;
+        Ok(())
+    }
+
+    fn path(data_dir: &Path) -> PathBuf {
+        data_dir.join(MANIFEST_FILE)
+    }
+}
@@ -0,0 +1,149 @@
+//! An in-memory [`Store`] implementation backed by nothing but a [`MemTable`]
+//!
+//! Unlike [`crate::storage::PersistentStore`], nothing here ever touches disk: there's
+//! no WAL and no SSTables, so data doesn't survive past the process exiting. It exists
+//! so the same [`Store`]-based behavior can be exercised without disk I/O -- e.g. a
+//! `--backend memory` CLI mode, or running one test suite against both backends to
+//! catch drift between them.
+
+use std::ops::Bound;
+
+use crate::error::Result;
+use crate::storage::{key_in_bounds, BatchOp, MemTable, Store, Value};
+
+/// A [`Store`] that keeps its entire dataset in a single in-memory [`MemTable`]
+///
+/// Every put/delete is still assigned a sequence number the same way
+/// [`crate::storage::PersistentStore`] does, so [`Store::get_at`]/[`Store::scan`]
+/// snapshot reads behave identically across both backends -- only durability differs.
+#[derive(Debug, Default)]
+pub struct InMemoryStore {
+    memtable: MemTable,
+}
+
+impl InMemoryStore {
+    /// Creates a new, empty in-memory store
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Store for InMemoryStore {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.get_at(key, u64::MAX)
+    }
+
+    fn put(&mut self, key: &[u8], value: Vec<u8>) -> Result<()> {
+        self.memtable.put(key, value);
+        Ok(())
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<()> {
+        self.memtable.delete(key);
+        Ok(())
+    }
+
+    fn write_batch(&mut self, ops: &[BatchOp]) -> Result<()> {
+        for op in ops {
+            match op {
+                BatchOp::Put { key, value } => {
+                    self.memtable.put(key.clone(), value.clone());
+                }
+                BatchOp::Delete { key } => {
+                    self.memtable.delete(key.clone());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn get_at(&self, key: &[u8], seq: u64) -> Result<Option<Vec<u8>>> {
+        Ok(self.memtable.get_at(key, seq).and_then(|v| match v {
+            Value::Value(v) => Some(v.clone()),
+            Value::Tombstone => None,
+        }))
+    }
+
+    fn current_seq(&self) -> u64 {
+        self.memtable.current_seq()
+    }
+
+    fn latest_seq(&self, key: &[u8]) -> Result<Option<u64>> {
+        Ok(self.memtable.seq_of(key))
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (Vec<u8>, Vec<u8>)> + '_> {
+        Box::new(self.memtable.iter().filter_map(|(k, v)| match v {
+            Value::Value(v) => Some((k.to_vec(), v.clone())),
+            Value::Tombstone => None,
+        }))
+    }
+
+    fn scan<'a>(
+        &'a self,
+        start: Bound<&'a [u8]>,
+        end: Bound<&'a [u8]>,
+        seq: Option<u64>,
+    ) -> Box<dyn Iterator<Item = (Vec<u8>, Vec<u8>)> + 'a> {
+        let seq = seq.unwrap_or(u64::MAX);
+        Box::new(
+            self.memtable
+                .iter_at(seq)
+                .filter(move |(key, _)| key_in_bounds(key, &start, &end))
+                .filter_map(|(k, v)| match v {
+                    Value::Value(v) => Some((k.to_vec(), v.clone())),
+                    Value::Tombstone => None,
+                }),
+        )
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn compact_range(&mut self, _start: Bound<&[u8]>, _end: Bound<&[u8]>) -> Result<()> {
+        // Nothing is ever written out of the MemTable, so there's nothing to merge.
+        Ok(())
+    }
+
+    fn set_min_live_seq(&mut self, _floor: Option<u64>) {
+        // No compaction ever happens against a MemTable, so there's nothing to pin.
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_memory_store_put_get_delete() -> Result<()> {
+        let mut store = InMemoryStore::new();
+
+        assert_eq!(store.get(b"key")?, None);
+
+        store.put(b"key", b"value".to_vec())?;
+        assert_eq!(store.get(b"key")?, Some(b"value".to_vec()));
+
+        store.delete(b"key")?;
+        assert_eq!(store.get(b"key")?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_in_memory_store_get_at_respects_snapshot_sequence() -> Result<()> {
+        let mut store = InMemoryStore::new();
+
+        store.put(b"key", b"value1".to_vec())?;
+        let snapshot_seq = store.current_seq();
+        store.put(b"key", b"value2".to_vec())?;
+
+        assert_eq!(
+            store.get_at(b"key", snapshot_seq)?,
+            Some(b"value1".to_vec())
+        );
+        assert_eq!(store.get(b"key")?, Some(b"value2".to_vec()));
+
+        Ok(())
+    }
+}
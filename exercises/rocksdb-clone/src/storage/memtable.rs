@@ -6,11 +6,14 @@
 //! ## Key Concepts
 //! - **MemTable**: An in-memory data structure that stores key-value pairs in sorted order.
 //! - **Tombstone**: A special marker indicating that a key has been deleted.
+//! - **Sequence numbers**: Every put/delete is assigned a monotonically increasing sequence
+//!   number, and multiple versions of the same user key can coexist so that snapshot reads
+//!   can see a consistent point-in-time view.
 //! - **Size Tracking**: The MemTable tracks its approximate size in bytes to determine when to flush to disk.
 
+use std::cmp::Ordering;
 use std::collections::BTreeMap;
 
-/// Represents an entry in the MemTable
 /// Represents the value stored in the MemTable
 ///
 /// In an LSM tree, values can be either actual data or tombstones.
@@ -27,11 +30,37 @@ pub enum Value {
     Tombstone,
 }
 
+/// The internal key a `MemTable` entry is sorted by: a user key paired with
+/// the sequence number of the write that produced it.
+///
+/// Entries compare by user key ascending, then by sequence number
+/// descending, so that versions of the same user key group together with
+/// the newest version first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct InternalKey {
+    user_key: Vec<u8>,
+    seq: u64,
+}
+
+impl Ord for InternalKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.user_key
+            .cmp(&other.user_key)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl PartialOrd for InternalKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 /// An in-memory key-value store that maintains keys in sorted order
 ///
 /// ## Implementation Details
-/// - Uses a `BTreeMap` for in-memory storage, which keeps keys sorted and allows for efficient
-///   range queries.
+/// - Uses a `BTreeMap` keyed by `(user_key, seq)` so multiple versions of the same
+///   user key can coexist, which makes snapshot reads possible.
 /// - Tracks the approximate size in bytes to determine when to flush to disk.
 /// - Implements tombstone markers for deleted keys to support consistent reads during compaction.
 ///
@@ -40,10 +69,12 @@ pub enum Value {
 /// This helps control memory usage and provides durability.
 #[derive(Debug, Default)]
 pub struct MemTable {
-    /// The actual key-value storage
-    map: BTreeMap<Vec<u8>, Value>,
+    entries: BTreeMap<InternalKey, Value>,
     /// Approximate size of the MemTable in bytes
     size: usize,
+    /// Sequence number assigned to the most recent write, or `0` if the
+    /// MemTable has never been written to.
+    last_seq: u64,
 }
 
 impl MemTable {
@@ -58,58 +89,92 @@ impl MemTable {
     /// ```
     pub fn new() -> Self {
         Self {
-            map: BTreeMap::new(),
+            entries: BTreeMap::new(),
             size: 0,
+            last_seq: 0,
         }
     }
 
-    /// Inserts a key-value pair into the MemTable
+    /// Creates a new, empty MemTable whose sequence counter continues from `seq`
+    /// instead of starting over at `0`
     ///
-    /// If the key already exists, its value will be updated and the old value will be returned.
-    /// The size tracking is automatically updated to reflect the change in storage requirements.
+    /// Used when an active MemTable is sealed into an immutable slot and replaced
+    /// with a fresh one: the replacement has to keep minting strictly increasing
+    /// sequence numbers, picking up from wherever the sealed MemTable left off,
+    /// rather than reusing sequence numbers the sealed data (or a snapshot of it)
+    /// already relies on.
+    pub(crate) fn new_continuing_from(seq: u64) -> Self {
+        Self {
+            entries: BTreeMap::new(),
+            size: 0,
+            last_seq: seq,
+        }
+    }
+
+    /// Assigns and returns the next sequence number
+    fn next_seq(&mut self) -> u64 {
+        self.last_seq += 1;
+        self.last_seq
+    }
+
+    /// Reserves the next sequence number without recording any entry under it
+    ///
+    /// Used by [`crate::storage::PersistentStore::write_batch`] to mint sequence
+    /// numbers for a batch's ops before they're framed into a WAL record, so the
+    /// record and the entries [`MemTable::put_at`]/[`MemTable::delete_at`] later
+    /// insert carry the same sequence.
+    pub(crate) fn reserve_seq(&mut self) -> u64 {
+        self.next_seq()
+    }
+
+    /// Inserts a new version of a key-value pair into the MemTable
+    ///
+    /// This does not overwrite any earlier version of the key: it is recorded as a new,
+    /// newer-sequenced entry so that readers holding an older snapshot keep seeing the
+    /// value as it was at their snapshot's sequence number.
     ///
     /// # Arguments
     /// * `key` - The key to insert
     /// * `value` - The value to insert
     ///
     /// # Returns
-    /// The previous value if the key existed, or `None` if it didn't
+    /// The sequence number assigned to this write
     ///
     /// # Examples
     /// ```
     /// use rocksdb_clone::storage::{MemTable, Value};
     ///
     /// let mut memtable = MemTable::new();
-    /// assert!(memtable.put(b"key", b"value1").is_none());
-    /// assert_eq!(memtable.put(b"key", b"value2"), Some(Value::Value(b"value1".to_vec())));
+    /// let seq = memtable.put(b"key", b"value1");
+    /// assert_eq!(memtable.get(b"key"), Some(&Value::Value(b"value1".to_vec())));
+    /// assert!(memtable.put(b"key", b"value2") > seq);
+    /// assert_eq!(memtable.get(b"key"), Some(&Value::Value(b"value2".to_vec())));
     /// ```
-    pub fn put(&mut self, key: impl Into<Vec<u8>>, value: impl Into<Vec<u8>>) -> Option<Value> {
+    pub fn put(&mut self, key: impl Into<Vec<u8>>, value: impl Into<Vec<u8>>) -> u64 {
+        let seq = self.next_seq();
+        self.put_at(key, value, seq);
+        seq
+    }
+
+    /// Inserts a new version of a key-value pair at an already-assigned sequence
+    /// number, instead of minting a fresh one
+    ///
+    /// Used when applying a [`crate::storage::WalOp`] (from a live write or WAL
+    /// replay) that already carries the sequence it was committed with, so the
+    /// MemTable entry matches exactly. `last_seq` is advanced to at least `seq`
+    /// so subsequent calls to [`MemTable::put`]/[`MemTable::delete`] keep
+    /// allocating strictly increasing numbers.
+    pub(crate) fn put_at(&mut self, key: impl Into<Vec<u8>>, value: impl Into<Vec<u8>>, seq: u64) {
         let key = key.into();
         let value = value.into();
-        let key_size = key.len();
-        let value_size = value.len();
-
-        let old_value = self.map.insert(key, Value::Value(value));
-
-        match &old_value {
-            Some(Value::Value(old_val)) => {
-                // Update size: remove old value size, add new value size
-                self.size = self.size - old_val.len() + value_size;
-            }
-            Some(Value::Tombstone) => {
-                // Replace tombstone with new value
-                self.size = self.size - 1 + value_size;
-            }
-            None => {
-                // New entry: add both key and value sizes
-                self.size += key_size + value_size;
-            }
-        }
+        self.last_seq = self.last_seq.max(seq);
 
-        old_value
+        self.size += key.len() + value.len();
+        self.entries
+            .insert(InternalKey { user_key: key, seq }, Value::Value(value));
     }
 
-    /// Retrieves a value by key
+    /// Retrieves the newest visible value for a key
     ///
     /// # Arguments
     /// * `key` - The key to look up
@@ -133,7 +198,37 @@ impl MemTable {
     /// assert_eq!(memtable.get(b"nonexistent"), None);
     /// ```
     pub fn get(&self, key: &[u8]) -> Option<&Value> {
-        self.map.get(key)
+        self.get_at(key, u64::MAX)
+    }
+
+    /// Retrieves the newest value for `key` that is visible at `seq`, i.e. whose own
+    /// sequence number is less than or equal to `seq`
+    ///
+    /// A visible `Value::Tombstone` means the key was deleted as of `seq` and should be
+    /// treated the same as "not found" by callers.
+    ///
+    /// # Examples
+    /// ```
+    /// use rocksdb_clone::storage::{MemTable, Value};
+    ///
+    /// let mut memtable = MemTable::new();
+    /// let seq1 = memtable.put(b"key", b"value1");
+    /// let seq2 = memtable.put(b"key", b"value2");
+    ///
+    /// assert_eq!(memtable.get_at(b"key", seq1), Some(&Value::Value(b"value1".to_vec())));
+    /// assert_eq!(memtable.get_at(b"key", seq2), Some(&Value::Value(b"value2".to_vec())));
+    /// ```
+    pub fn get_at(&self, key: &[u8], seq: u64) -> Option<&Value> {
+        let lower_bound = InternalKey {
+            user_key: key.to_vec(),
+            seq,
+        };
+
+        self.entries
+            .range(lower_bound..)
+            .next()
+            .filter(|(ik, _)| ik.user_key == key)
+            .map(|(_, v)| v)
     }
 
     /// Deletes a key from the MemTable by inserting a tombstone
@@ -145,7 +240,7 @@ impl MemTable {
     /// * `key` - The key to delete
     ///
     /// # Returns
-    /// The previous value if the key existed, or `None` if it didn't
+    /// The sequence number assigned to this delete
     ///
     /// # Examples
     /// ```
@@ -153,37 +248,30 @@ impl MemTable {
     ///
     /// let mut memtable = MemTable::new();
     /// memtable.put(b"key", b"value");
-    /// assert_eq!(memtable.delete(b"key"), Some(Value::Value(b"value".to_vec())));
+    /// memtable.delete(b"key");
     /// assert_eq!(memtable.get(b"key"), Some(&Value::Tombstone));
-    ///
-    /// // Deleting a non-existent key
-    /// assert_eq!(memtable.delete(b"nonexistent"), None);
     /// ```
-    pub fn delete<K: Into<Vec<u8>>>(&mut self, key: K) -> Option<Value> {
-        let key = key.into();
-        let old_value = self.map.insert(key.clone(), Value::Tombstone);
+    pub fn delete<K: Into<Vec<u8>>>(&mut self, key: K) -> u64 {
+        let seq = self.next_seq();
+        self.delete_at(key, seq);
+        seq
+    }
 
-        match &old_value {
-            Some(Value::Value(val)) => {
-                // Replace value with tombstone: remove value size, add 1 byte for tombstone
-                self.size = self.size - val.len() + 1;
-            }
-            Some(Value::Tombstone) => {
-                // Already a tombstone, no size change
-            }
-            None => {
-                // New tombstone: add key size + 1 byte for tombstone
-                self.size += key.len() + 1;
-            }
-        }
+    /// Inserts a tombstone at an already-assigned sequence number; see
+    /// [`MemTable::put_at`] for why this exists alongside [`MemTable::delete`]
+    pub(crate) fn delete_at<K: Into<Vec<u8>>>(&mut self, key: K, seq: u64) {
+        let key = key.into();
+        self.last_seq = self.last_seq.max(seq);
 
-        old_value
+        self.size += key.len() + 1;
+        self.entries
+            .insert(InternalKey { user_key: key, seq }, Value::Tombstone);
     }
 
-    /// Returns an iterator over the entries in the MemTable
+    /// Returns an iterator over the newest visible version of each key in the MemTable
     ///
-    /// The iterator yields key-value pairs in sorted order by key.
-    /// Both regular values and tombstones are included in the iteration.
+    /// The iterator yields key-value pairs in sorted order by key, one entry per distinct
+    /// user key (its newest version). Both regular values and tombstones are included.
     ///
     /// # Examples
     /// ```
@@ -199,15 +287,86 @@ impl MemTable {
     /// assert_eq!(iter.next(), None);
     /// ```
     pub fn iter(&self) -> impl Iterator<Item = (&[u8], &Value)> + '_ {
-        self.map.iter().map(|(k, v)| (k.as_slice(), v))
+        self.iter_at(u64::MAX)
+    }
+
+    /// Returns an iterator over the newest version of each key visible at `seq`
+    ///
+    /// Like [`MemTable::iter`], but any version whose sequence number exceeds `seq`
+    /// is skipped, so the iterator reflects a consistent point-in-time view.
+    ///
+    /// # Examples
+    /// ```
+    /// use rocksdb_clone::storage::{MemTable, Value};
+    ///
+    /// let mut memtable = MemTable::new();
+    /// let seq1 = memtable.put(b"key", b"value1");
+    /// memtable.put(b"key", b"value2");
+    ///
+    /// let mut iter = memtable.iter_at(seq1);
+    /// assert_eq!(iter.next(), Some((&b"key"[..], &Value::Value(b"value1".to_vec()))));
+    /// assert_eq!(iter.next(), None);
+    /// ```
+    pub fn iter_at(&self, seq: u64) -> impl Iterator<Item = (&[u8], &Value)> + '_ {
+        let mut last_key: Option<&[u8]> = None;
+        self.entries.iter().filter_map(move |(ik, v)| {
+            if ik.seq > seq {
+                return None;
+            }
+            let key = ik.user_key.as_slice();
+            if last_key == Some(key) {
+                None
+            } else {
+                last_key = Some(key);
+                Some((key, v))
+            }
+        })
+    }
+
+    /// Returns the current sequence number, i.e. the sequence assigned to the most
+    /// recent write, or `0` if the MemTable has never been written to
+    pub fn current_seq(&self) -> u64 {
+        self.last_seq
+    }
+
+    /// Returns every version of every key, newest-first within each key's run,
+    /// exactly as stored -- unlike [`MemTable::iter`]/[`MemTable::iter_at`],
+    /// nothing is deduped or filtered by sequence number
+    ///
+    /// Used by [`crate::storage::PersistentStore::flush_sealed_memtable`] to carry
+    /// every version down into the flushed SSTable, so a snapshot taken before the
+    /// flush still has the version it saw to read afterward.
+    pub(crate) fn iter_all(&self) -> impl Iterator<Item = (&[u8], u64, &Value)> + '_ {
+        self.entries
+            .iter()
+            .map(|(ik, v)| (ik.user_key.as_slice(), ik.seq, v))
+    }
+
+    /// Returns the sequence number of the newest entry (value or tombstone) for
+    /// `key`, or `None` if it has never been written
+    ///
+    /// Used by [`crate::storage::PersistentStore::latest_seq`] to detect whether a
+    /// key has been written since a transaction's snapshot was taken, regardless
+    /// of whether the value it was given back is unchanged (see
+    /// [`crate::txn`] for why a value comparison alone isn't enough).
+    pub(crate) fn seq_of(&self, key: &[u8]) -> Option<u64> {
+        let lower_bound = InternalKey {
+            user_key: key.to_vec(),
+            seq: u64::MAX,
+        };
+
+        self.entries
+            .range(lower_bound..)
+            .next()
+            .filter(|(ik, _)| ik.user_key == key)
+            .map(|(ik, _)| ik.seq)
     }
 
     /// Returns the approximate size of the MemTable in bytes
     ///
-    /// The size includes:
-    /// - The size of all keys
-    /// - The size of all values (for Value::Value variants)
-    /// - 1 byte per tombstone (for Value::Tombstone variants)
+    /// The size includes every stored version of every key: each put adds its key and
+    /// value sizes and each delete adds its key size plus 1 byte for the tombstone, since
+    /// older versions are retained (not overwritten) to support snapshot reads.
     ///
     /// This is an approximation used to determine when to flush the MemTable to disk.
     ///
@@ -221,10 +380,6 @@ impl MemTable {
     /// // Key "a" (1 byte) + Value "value" (5 bytes) = 6 bytes
     /// memtable.put(b"a", b"value");
     /// assert_eq!(memtable.size(), 6);
-    ///
-    /// // Deleting replaces the value with a 1-byte tombstone
-    /// memtable.delete(b"a");
-    /// assert_eq!(memtable.size(), 2); // 1 byte key + 1 byte tombstone
     /// ```
     pub fn size(&self) -> usize {
         self.size
@@ -241,20 +396,15 @@ impl MemTable {
     ///
     /// memtable.put(b"key", b"value");
     /// assert!(!memtable.is_empty());
-    ///
-    /// memtable.delete(b"key");
-    /// assert!(!memtable.is_empty()); // Still contains a tombstone
-    ///
-    /// let mut empty_memtable = MemTable::new();
-    /// assert!(empty_memtable.is_empty());
     /// ```
     pub fn is_empty(&self) -> bool {
-        self.map.is_empty()
+        self.entries.is_empty()
     }
 
     /// Clears the MemTable, removing all key-value pairs
     ///
-    /// This resets the MemTable to its initial empty state.
+    /// This resets the MemTable to its initial empty state. The sequence counter is left
+    /// untouched so sequence numbers stay monotonically increasing across flushes.
     ///
     /// # Examples
     /// ```
@@ -269,7 +419,7 @@ impl MemTable {
     /// assert_eq!(memtable.size(), 0);
     /// ```
     pub fn clear(&mut self) {
-        self.map.clear();
+        self.entries.clear();
         self.size = 0;
     }
 }
@@ -285,14 +435,12 @@ mod tests {
         let key1 = b"key1".to_vec();
         let value1 = b"value1".to_vec();
 
-        assert!(memtable.put(key1.clone(), value1.clone()).is_none());
-        assert_eq!(memtable.get(&key1), Some(&Value::Value(value1.clone())));
+        let seq1 = memtable.put(key1.clone(), value1.clone());
+        assert_eq!(memtable.get(&key1), Some(&Value::Value(value1)));
 
         let value2 = b"value2".to_vec();
-        assert_eq!(
-            memtable.put(key1.clone(), value2.clone()),
-            Some(Value::Value(value1))
-        );
+        let seq2 = memtable.put(key1.clone(), value2.clone());
+        assert!(seq2 > seq1);
         assert_eq!(memtable.get(&key1), Some(&Value::Value(value2)));
 
         assert_eq!(memtable.get(b"nonexistent"), None);
@@ -310,8 +458,29 @@ mod tests {
 
         memtable.delete(key.clone());
         assert_eq!(memtable.get(&key), Some(&Value::Tombstone));
+    }
+
+    #[test]
+    fn test_memtable_snapshot_reads() {
+        let mut memtable = MemTable::new();
+
+        let seq1 = memtable.put(b"key", b"value1");
+        let seq2 = memtable.put(b"key", b"value2");
+        let seq3 = memtable.delete(b"key");
+
+        assert_eq!(
+            memtable.get_at(b"key", seq1),
+            Some(&Value::Value(b"value1".to_vec()))
+        );
+        assert_eq!(
+            memtable.get_at(b"key", seq2),
+            Some(&Value::Value(b"value2".to_vec()))
+        );
+        assert_eq!(memtable.get_at(b"key", seq3), Some(&Value::Tombstone));
+        assert_eq!(memtable.get(b"key"), Some(&Value::Tombstone));
 
-        assert!(memtable.delete(b"nonexistent".to_vec()).is_none());
+        // A snapshot taken before the key ever existed sees nothing.
+        assert_eq!(memtable.get_at(b"key", 0), None);
     }
 
     #[test]
@@ -322,28 +491,35 @@ mod tests {
 
         let key1 = b"key1".to_vec();
         let value1 = b"value1".to_vec();
-        let value2 = b"value2".to_vec();
-        let value3 = b"new_value1".to_vec();
 
-        // Size should be key length + value length
-        assert_eq!(memtable.size(), 0);
         memtable.put(key1.clone(), value1);
         assert_eq!(memtable.size(), 4 + 6); // "key1" (4) + "value1" (6)
 
-        // Update with a different value of same length
-        memtable.put(key1.clone(), value2);
-        assert_eq!(memtable.size(), 4 + 6); // "key1" (4) + "value2" (6)
-
-        // Update with a longer value
-        memtable.put(key1.clone(), value3);
-        assert_eq!(memtable.size(), 4 + 10); // "key1" (4) + "new_value1" (10)
-
-        // Delete the key (replaces with tombstone)
         memtable.delete(key1.clone());
-        // Tombstone adds 1 byte to the size (4 bytes for key + 1 byte for tombstone)
-        assert_eq!(memtable.size(), 5); // Key size (4) + tombstone (1)
+        // Older version is retained for snapshot reads, plus the new tombstone.
+        assert_eq!(memtable.size(), (4 + 6) + (4 + 1));
 
         memtable.clear();
         assert_eq!(memtable.size(), 0);
     }
+
+    #[test]
+    fn test_memtable_iter_yields_newest_version_per_key() {
+        let mut memtable = MemTable::new();
+
+        memtable.put(b"b", b"value_b");
+        memtable.put(b"a", b"value_a1");
+        memtable.put(b"a", b"value_a2");
+
+        let mut iter = memtable.iter();
+        assert_eq!(
+            iter.next(),
+            Some((&b"a"[..], &Value::Value(b"value_a2".to_vec())))
+        );
+        assert_eq!(
+            iter.next(),
+            Some((&b"b"[..], &Value::Value(b"value_b".to_vec())))
+        );
+        assert_eq!(iter.next(), None);
+    }
 }
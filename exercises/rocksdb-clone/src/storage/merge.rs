@@ -0,0 +1,271 @@
+//! K-way merging iterators over ordered key-value sources
+//!
+//! [`MergingIterator`] presents a single ordered view of the LSM tree made up of the
+//! active MemTable and the on-disk SSTables. When the same user key appears in more
+//! than one source, only the version from the newest source is yielded, and that
+//! version is suppressed entirely if it is a tombstone.
+//!
+//! [`VersionMergeIterator`] instead keeps every version of every key, for
+//! compaction: see its own docs for why collapsing to the newest would be unsafe
+//! there.
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+/// One entry pulled from a source, ready to be ordered in the merge heap
+struct HeapEntry {
+    key: Vec<u8>,
+    value: Option<Vec<u8>>,
+    /// Index of the source this entry came from; lower ranks are newer and win ties
+    /// on the same key.
+    source: usize,
+}
+
+impl PartialEq for HeapEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key && self.source == other.source
+    }
+}
+
+impl Eq for HeapEntry {}
+
+impl PartialOrd for HeapEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for HeapEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // `BinaryHeap` is a max-heap; reverse the key order so the smallest key is
+        // popped first, and prefer the newest source (smaller rank) on ties.
+        other
+            .key
+            .cmp(&self.key)
+            .then_with(|| other.source.cmp(&self.source))
+    }
+}
+
+/// A k-way merge over several sources ordered newest (index `0`) to oldest
+///
+/// Yields `(key, value)` pairs in ascending key order: for a key present in several
+/// sources only the newest version is returned, and a tombstone suppresses the key
+/// from the output entirely.
+pub struct MergingIterator<'a> {
+    sources: Vec<Box<dyn Iterator<Item = (Vec<u8>, Option<Vec<u8>>)> + 'a>>,
+    heap: BinaryHeap<HeapEntry>,
+}
+
+impl<'a> MergingIterator<'a> {
+    /// Creates a merging iterator over `sources`, ordered from newest (index `0`) to oldest
+    pub fn new(mut sources: Vec<Box<dyn Iterator<Item = (Vec<u8>, Option<Vec<u8>>)> + 'a>>) -> Self {
+        let mut heap = BinaryHeap::with_capacity(sources.len());
+        for (source, iter) in sources.iter_mut().enumerate() {
+            if let Some((key, value)) = iter.next() {
+                heap.push(HeapEntry { key, value, source });
+            }
+        }
+        Self { sources, heap }
+    }
+
+    fn pull(&mut self, source: usize) {
+        if let Some((key, value)) = self.sources[source].next() {
+            self.heap.push(HeapEntry { key, value, source });
+        }
+    }
+
+    /// Advances the merge and returns the next key's newest value, same as
+    /// `Iterator::next` but without suppressing tombstones
+    ///
+    /// Used by compaction, which needs to see a tombstone to decide whether it can
+    /// be dropped or must be carried forward to a deeper level.
+    pub(crate) fn next_raw(&mut self) -> Option<(Vec<u8>, Option<Vec<u8>>)> {
+        let winner = self.heap.pop()?;
+        self.pull(winner.source);
+
+        // Any other sources holding the same (now-shadowed) key must be drained
+        // and refilled so they don't resurface on a later call.
+        while let Some(next) = self.heap.peek() {
+            if next.key != winner.key {
+                break;
+            }
+            let shadowed = self.heap.pop().expect("peeked entry must be present");
+            self.pull(shadowed.source);
+        }
+
+        Some((winner.key, winner.value))
+    }
+}
+
+impl<'a> Iterator for MergingIterator<'a> {
+    type Item = (Vec<u8>, Vec<u8>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (key, value) = self.next_raw()?;
+            if let Some(value) = value {
+                return Some((key, value));
+            }
+            // Tombstone: the key is deleted as of the newest source, skip it and
+            // continue merging.
+        }
+    }
+}
+
+/// One entry pulled from a source for [`VersionMergeIterator`], ready to be
+/// ordered in the merge heap
+struct VersionHeapEntry {
+    key: Vec<u8>,
+    seq: u64,
+    value: Option<Vec<u8>>,
+    /// Index of the source this entry came from, so `next` knows which source to
+    /// pull from once this entry wins.
+    source: usize,
+}
+
+impl PartialEq for VersionHeapEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key && self.seq == other.seq
+    }
+}
+
+impl Eq for VersionHeapEntry {}
+
+impl PartialOrd for VersionHeapEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for VersionHeapEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // `BinaryHeap` is a max-heap; reverse the key order so the smallest key is
+        // popped first, and among versions of the same key, pop the newest (highest
+        // `seq`) first.
+        other.key.cmp(&self.key).then_with(|| self.seq.cmp(&other.seq))
+    }
+}
+
+/// A k-way merge over several sources' full version histories, preserving every
+/// `(key, seq, value)` instead of collapsing a key down to its newest version
+///
+/// Used by compaction: unlike [`MergingIterator`], which only keeps the newest
+/// source's version of a duplicate key, this keeps every version any input table
+/// held, so a [`crate::Snapshot`](crate::Snapshot) taken before the compaction can
+/// still find the version it saw. Yields entries ascending by key, then
+/// descending by sequence number within a key -- the same order
+/// [`crate::storage::SSTable::write_batch`] requires.
+pub struct VersionMergeIterator<'a> {
+    sources: Vec<Box<dyn Iterator<Item = (Vec<u8>, u64, Option<Vec<u8>>)> + 'a>>,
+    heap: BinaryHeap<VersionHeapEntry>,
+}
+
+impl<'a> VersionMergeIterator<'a> {
+    /// Creates a version-preserving merge over `sources`; unlike
+    /// [`MergingIterator::new`], source order doesn't matter since nothing is
+    /// shadowed or dropped -- sequence numbers alone decide output order
+    pub fn new(mut sources: Vec<Box<dyn Iterator<Item = (Vec<u8>, u64, Option<Vec<u8>>)> + 'a>>) -> Self {
+        let mut heap = BinaryHeap::with_capacity(sources.len());
+        for (source, iter) in sources.iter_mut().enumerate() {
+            if let Some((key, seq, value)) = iter.next() {
+                heap.push(VersionHeapEntry { key, seq, value, source });
+            }
+        }
+        Self { sources, heap }
+    }
+
+    fn pull(&mut self, source: usize) {
+        if let Some((key, seq, value)) = self.sources[source].next() {
+            self.heap.push(VersionHeapEntry { key, seq, value, source });
+        }
+    }
+}
+
+impl<'a> Iterator for VersionMergeIterator<'a> {
+    type Item = (Vec<u8>, u64, Option<Vec<u8>>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let winner = self.heap.pop()?;
+        self.pull(winner.source);
+        Some((winner.key, winner.seq, winner.value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source<'a>(entries: Vec<(&'a str, Option<&'a str>)>) -> Box<dyn Iterator<Item = (Vec<u8>, Option<Vec<u8>>)> + 'a> {
+        Box::new(
+            entries
+                .into_iter()
+                .map(|(k, v)| (k.as_bytes().to_vec(), v.map(|v| v.as_bytes().to_vec()))),
+        )
+    }
+
+    #[test]
+    fn test_merge_prefers_newest_source_on_duplicate_keys() {
+        let newest = source(vec![("a", Some("new_a"))]);
+        let oldest = source(vec![("a", Some("old_a")), ("b", Some("old_b"))]);
+
+        let merged: Vec<_> = MergingIterator::new(vec![newest, oldest]).collect();
+
+        assert_eq!(
+            merged,
+            vec![
+                (b"a".to_vec(), b"new_a".to_vec()),
+                (b"b".to_vec(), b"old_b".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_suppresses_tombstoned_keys() {
+        let newest = source(vec![("a", None)]);
+        let oldest = source(vec![("a", Some("old_a")), ("b", Some("old_b"))]);
+
+        let merged: Vec<_> = MergingIterator::new(vec![newest, oldest]).collect();
+
+        assert_eq!(merged, vec![(b"b".to_vec(), b"old_b".to_vec())]);
+    }
+
+    fn version_source<'a>(
+        entries: Vec<(&'a str, u64, Option<&'a str>)>,
+    ) -> Box<dyn Iterator<Item = (Vec<u8>, u64, Option<Vec<u8>>)> + 'a> {
+        Box::new(
+            entries
+                .into_iter()
+                .map(|(k, seq, v)| (k.as_bytes().to_vec(), seq, v.map(|v| v.as_bytes().to_vec()))),
+        )
+    }
+
+    #[test]
+    fn test_version_merge_keeps_every_version_of_a_duplicate_key() {
+        let newer = version_source(vec![("a", 5, Some("new_a"))]);
+        let older = version_source(vec![("a", 1, Some("old_a")), ("b", 2, Some("b"))]);
+
+        let merged: Vec<_> = VersionMergeIterator::new(vec![newer, older]).collect();
+
+        assert_eq!(
+            merged,
+            vec![
+                (b"a".to_vec(), 5, Some(b"new_a".to_vec())),
+                (b"a".to_vec(), 1, Some(b"old_a".to_vec())),
+                (b"b".to_vec(), 2, Some(b"b".to_vec())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_version_merge_does_not_suppress_tombstoned_keys() {
+        let newer = version_source(vec![("a", 3, None)]);
+        let older = version_source(vec![("a", 1, Some("old_a"))]);
+
+        let merged: Vec<_> = VersionMergeIterator::new(vec![newer, older]).collect();
+
+        assert_eq!(
+            merged,
+            vec![(b"a".to_vec(), 3, None), (b"a".to_vec(), 1, Some(b"old_a".to_vec()))]
+        );
+    }
+}
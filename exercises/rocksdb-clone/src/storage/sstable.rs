@@ -3,29 +3,149 @@
 //! # File Format
 //!
 //! SSTables are immutable, sorted files that store key-value pairs. They consist of:
-//! 1. Data section: Sequence of key-value pairs
-//! 2. Index section: Maps keys to their offsets in the data section
-//! 3. Metadata: Information about the SSTable (number of entries, data size, index size)
-//! 4. Magic number: For file format validation
+//! 1. Data section: Entries partitioned into ~4 KB blocks (see `# Block Format` below)
+//! 2. Index section: Maps each data block's last key to its `(offset, size)` handle
+//! 3. Filter section: A Bloom filter block covering every key (see `# Filtering` below)
+//! 4. Metadata: Information about the SSTable (number of entries, section sizes)
+//! 5. Footer: Magic number, metadata location, and checksum algorithm tag (see
+//!    `# Checksums`)
+//!
+//! # Block Format
+//!
+//! Entries are partitioned into [`BLOCK_SIZE_TARGET`]-sized data blocks. Within a
+//! block, consecutive keys are prefix-compressed: each entry is `shared_len`,
+//! `non_shared_len`, `seq`, `value_len` (LEB128 varints), followed by the
+//! non-shared key bytes and the value. `seq` is the sequence number of the write
+//! that produced this particular version of the key, the same sequence number
+//! [`crate::storage::MemTable`] sorts by -- multiple versions of a key are written
+//! newest-first (highest `seq` first), exactly mirroring `MemTable`'s own
+//! ascending-key/descending-seq ordering, so a reader can stop at the first
+//! version whose `seq` is visible at a given snapshot. Every [`RESTART_INTERVAL`]
+//! entries, `shared_len` is forced to `0` and the key is written in full -- a
+//! "restart point" -- so a reader can binary-search a block's restart array
+//! instead of scanning it from the start. A block's restart offsets and their
+//! count (both fixed `u32`s) are appended after its last entry, followed by a
+//! 4-byte CRC-32 trailer covering everything written so far (see `# Checksums`).
+//! See [`BlockBuilder`] for the writer side and [`decode_block_entry`] for the
+//! reader side.
+//!
+//! # Checksums
+//!
+//! Every data block and the index section carry a trailing 4-byte CRC-32 (see
+//! [`crate::storage::checksum`]), checked before the block or section is parsed so
+//! silent bit rot surfaces as an [`crate::error::Error::Corruption`] naming the
+//! offset and the expected and actual CRC, rather than a confusing decode failure
+//! further down. The algorithm is recorded as a one-byte tag in the footer, so a
+//! stronger algorithm can be added later without breaking tables already on disk.
 //!
 //! # Tombstone Handling
 //!
 //! Tombstones (deletions) are represented by a special value length (u64::MAX) with no data.
 //! When reading, a tombstone is returned as `None`.
 //!
+//! # Compression
+//!
+//! Every data block is compressed independently with the codec configured via
+//! [`crate::config::Compression`], mirroring how LevelDB-style tables record a
+//! compression byte alongside each block's checksum: [`pack_block`] appends a
+//! one-byte tag naming the codec before the checksum trailer, so [`unpack_block`]
+//! (and therefore any future reader) never needs external configuration to decode a
+//! block, and a table keeps reading correctly even after the database's default
+//! codec changes. See [`crate::storage::compression`] for the codec registry. The
+//! table's configured default is also recorded in its metadata for convenience, but
+//! isn't needed to decode any particular block. The index and filter sections are
+//! left uncompressed (see [`index_codec`]), since both are read on every lookup to
+//! find the data worth decompressing in the first place.
+//!
+//! # Encryption
+//!
+//! A table can additionally seal its data and index blocks with an AEAD cipher
+//! (see [`crate::storage::encryption`]) via [`SSTable::create_with_encryption`] /
+//! [`SSTableBuilder::new_with_encryption`], and reopen them via
+//! [`SSTable::open_with_encryption`]. The cipher's key is derived from a
+//! passphrase and a random per-file salt recorded in the table's metadata, never
+//! from the passphrase alone, so the same passphrase still produces a different
+//! key for every table; the key itself is never written to disk. Each block
+//! carries its own one-byte encryption tag in its trailer, the same way it
+//! carries a compression tag, so [`unpack_block`] never needs external
+//! configuration to know whether (or how) a block was sealed -- only the key.
+//!
+//! # Filtering
+//!
+//! The filter section holds a Bloom filter built from every key in the table (see
+//! [`crate::storage::bloom`]), loaded into memory once at [`SSTable::open`]. [`SSTable::get`]
+//! consults it before touching the index or data sections and returns `None` outright on
+//! a negative, at the cost of a configurable false-positive rate
+//! (see [`crate::config::Config::bits_per_key`]). Its size is recorded in
+//! [`SSTableMeta`] (it starts right after the index block) rather than in the
+//! fixed-size footer, the same as the compression and
+//! encryption tags, so the footer's layout never has to change when a new field is added.
+//!
 //! # Lookup Process
 //!
-//! 1. Check the in-memory index to find the key's offset in the data section
-//! 2. If found, seek to the offset and read the value
-//! 3. If the value length is u64::MAX, it's a tombstone and we return None
-//! 4. If not found in the index, the key doesn't exist in this SSTable
+//! 1. Check the filter; if it rules the key out, return `None` without reading further
+//! 2. Unpack the index block (verify its checksum, then decompress it) and
+//!    binary-search it for the one data block that could hold the key
+//! 3. Unpack that one data block and binary-search its restart array
+//! 4. Scan forward from the nearest restart, rebuilding keys from their shared
+//!    prefix, until a version of the key visible at the requested sequence number
+//!    is found, the key is passed, or the block ends -- a version newer than
+//!    requested is skipped rather than returned, since an older (visible) one may
+//!    still follow it (see `# Block Format`)
+//! 5. If the value length is u64::MAX, it's a tombstone and we return None
+//!
+//! [`SSTable::range_at`] (and [`SSTable::range`]/[`SSTable::iter`], both defined in
+//! terms of it) follows the same index seek as step 2 to find the first block that
+//! could hold a key in range, then decodes forward from there instead of from the
+//! start of the table, stopping as soon as a decoded key passes the range's end,
+//! and collapsing each key's visible versions down to the one its caller asked for.
+//!
+//! This is the LevelDB-style block layout this crate has used from early on --
+//! `shared_len`/`non_shared_len`/`value_len` are LEB128 varints here rather than
+//! fixed-width lengths, which shrinks small entries further than a fixed encoding
+//! would, at no cost to the restart-point binary search since restart entries are
+//! located by byte offset, not by a fixed stride.
+//!
+//! # Memory-Mapped Reads
+//!
+//! When [`crate::config::Config::use_mmap`] is set (and the crate's `mmap` feature is
+//! enabled), a table's file is memory-mapped once at open and refreshed after every
+//! [`SSTable::write_batch`], and every read path slices directly into the mapping
+//! instead of issuing its own read syscall.
 //!
 //! # Write Process
 //!
-//! 1. Write all key-value pairs to the data section, keeping track of offsets
-//! 2. Write the index section with key-offset mappings
-//! 3. Write metadata (number of entries, data size, index size)
-//! 4. Write magic number for validation
+//! 1. Partition entries into prefix-compressed data blocks (see `# Block Format`),
+//!    packing (compressing, tagging, checksumming) each one as it's finished
+//! 2. Build the index block (each data block's last key and handle) and pack it
+//!    the same way, but uncompressed (see `# Compression`)
+//! 3. Build and write the (uncompressed) filter section
+//! 4. Write metadata (number of entries, section sizes, default compression tag)
+//! 5. Write the footer: magic number, metadata location, checksum algorithm tag,
+//!    and format version (see `# Format Versioning`)
+//!
+//! [`SSTable::write_batch`] requires every entry up front as a single slice;
+//! [`SSTableBuilder`] streams the same steps incrementally instead, for callers
+//! (like compaction) that produce entries one at a time and would otherwise have
+//! to buffer the whole output table in memory first.
+//!
+//! # Dump and Restore
+//!
+//! [`SSTable::dump`] streams the newest version of every key (including
+//! tombstones; older versions aren't dumped) as JSON lines --
+//! a header with the table's metadata, then one line per entry with its key and
+//! value hex-encoded for binary safety -- and [`SSTable::restore`] rebuilds a
+//! table from that format via [`SSTableBuilder`]. This gives operators a way to
+//! inspect, diff, or rebuild a table by hand without a running database, the same
+//! role an `ldb dump`/`load` style tool plays for other LSM-tree stores.
+//!
+//! # Format Versioning
+//!
+//! The footer's last byte is a format-version tag (see
+//! [`crate::storage::CURRENT_FORMAT_VERSION`]); [`SSTable::open`] refuses a table
+//! tagged with any other version rather than risk silently misreading an encoding
+//! it doesn't understand. [`storage::upgrade_data_dir`](crate::storage::upgrade_data_dir)
+//! rewrites such a table at the current version, backing up the original first.
 //!
 //! # Implementation Notes
 //!
@@ -33,15 +153,34 @@
 //! - All numbers are stored in big-endian format for consistency
 //! - The file is truncated and rewritten on each write to ensure consistency
 
+use crate::config::{Compression, EncryptionType};
 use crate::error::{Error, Result};
+use crate::storage::bloom::{self, FilterPolicy};
+use crate::storage::checksum::{self, ChecksumTag};
+use crate::storage::compression::{CompressionTag, CompressorRegistry};
+use crate::storage::encryption::{self, EncryptionRegistry, EncryptionTag};
+use crate::storage::CURRENT_FORMAT_VERSION;
 use bincode::{Decode, Encode};
 use serde::{Deserialize, Serialize};
 use std::{
+    borrow::Cow,
     fs::{File, OpenOptions},
-    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    io::{BufRead, BufWriter, Read, Seek, SeekFrom, Write},
+    ops::Bound,
     path::{Path, PathBuf},
+    sync::Arc,
 };
 
+/// Default bits of Bloom filter budgeted per key, giving roughly a 1% false-positive rate
+const DEFAULT_BITS_PER_KEY: usize = 10;
+
+/// The memory-mapped region type backing [`SSTable::mmap`] when the `mmap` feature
+/// is enabled, or a stand-in when it isn't (in which case the field is always `None`)
+#[cfg(feature = "mmap")]
+type Mapping = memmap2::Mmap;
+#[cfg(not(feature = "mmap"))]
+type Mapping = ();
+
 // Magic number for SSTable file format validation
 #[allow(dead_code)]
 /// Magic number used to identify SSTable files
@@ -58,20 +197,755 @@ const MAGIC_NUMBER: u64 = 0x1234567890ABCDEF;
 pub struct SSTable {
     file: File,
     path: PathBuf,
+    /// Codec used to compress the data section on the next [`SSTable::write_batch`]
+    compression: Compression,
+    /// Maps compression tags to codecs, so a file written under a codec other than
+    /// `compression` (e.g. by an older build) can still be read back
+    registry: CompressorRegistry,
+    /// Policy used to build the filter block on the next [`SSTable::write_batch`]
+    filter_policy: Arc<dyn FilterPolicy>,
+    /// This table's filter block, loaded once at [`SSTable::open`] (or built by
+    /// [`SSTable::write_batch`]) so [`SSTable::get`] never has to re-read it
+    filter: Vec<u8>,
+    /// Whether to read this table's file via memory mapping instead of buffered I/O
+    use_mmap: bool,
+    /// The table's file, memory-mapped, when `use_mmap` is set and the `mmap`
+    /// feature is enabled; refreshed by [`SSTable::refresh_mmap`] whenever the
+    /// underlying file's contents change
+    mmap: Option<Mapping>,
+    /// Cipher this table is configured to seal new blocks with on the next
+    /// [`SSTable::write_batch`]
+    encryption: EncryptionType,
+    /// This table's data key, derived once (see [`encryption::derive_key`]) from a
+    /// passphrase and `salt`; `None` when `encryption` is [`EncryptionType::None`]
+    key: Option<[u8; encryption::KEY_LEN]>,
+    /// Random per-file salt the data key was derived with; meaningless when
+    /// `encryption` is [`EncryptionType::None`]
+    salt: [u8; encryption::SALT_LEN],
+    /// Maps encryption tags to ciphers, so a file sealed under a cipher other than
+    /// `encryption` (e.g. by an older build) can still be opened
+    encryption_registry: EncryptionRegistry,
+}
+
+/// Bundles the settings needed to pack or unpack a block, so [`pack_block`] and
+/// [`unpack_block`] don't need half a dozen positional parameters apiece
+struct BlockCodec<'a> {
+    compression: Compression,
+    compression_registry: &'a CompressorRegistry,
+    encryption: EncryptionType,
+    encryption_registry: &'a EncryptionRegistry,
+    key: Option<&'a [u8; encryption::KEY_LEN]>,
 }
 
-/// Size of the SSTable footer in bytes (magic number + metadata length + metadata offset)
-const FOOTER_SIZE: u64 = 24;
+/// Size of the SSTable footer in bytes: magic number (8) + metadata length (8) +
+/// metadata offset (8) + checksum algorithm tag (1) + format version (1)
+const FOOTER_SIZE: u64 = 26;
 
 /// Represents the metadata for an SSTable
 #[derive(Debug, Serialize, Deserialize, Encode, Decode)]
 struct SSTableMeta {
     /// Number of entries in the SSTable
     num_entries: u64,
-    /// Size of the data section in bytes
+    /// Size of the data section on disk, in bytes (the sum of every packed block)
+    data_size: u64,
+    /// Size of the index section in bytes (the packed index block)
+    index_size: u64,
+    /// Size of the filter section in bytes (`0` if no filter was built)
+    filter_size: u64,
+    /// Codec this table was configured to compress new blocks with; recorded so a
+    /// reader knows it without external configuration, but not needed to decode any
+    /// particular block, since every block carries its own compression tag (see
+    /// [`pack_block`])
+    compression_tag: CompressionTag,
+    /// Cipher this table was configured to seal new blocks with; recorded so a
+    /// reader knows it without external configuration (e.g. to tell whether a
+    /// passphrase is required at all), but not needed to decode any particular
+    /// block, since every block carries its own encryption tag (see
+    /// [`pack_block`])
+    encryption_tag: EncryptionTag,
+    /// Random per-file salt [`encryption::derive_key`] was given to derive this
+    /// table's data key; all zero and unused when `encryption_tag` is
+    /// [`encryption::TAG_NONE`]
+    salt: [u8; encryption::SALT_LEN],
+    /// This table's smallest and largest key (empty when `num_entries` is `0`),
+    /// so compaction can tell whether two tables' key ranges overlap without
+    /// reading either one's data section
+    min_key: Vec<u8>,
+    max_key: Vec<u8>,
+}
+
+/// Parses the footer and metadata block out of a whole SSTable file's `bytes`,
+/// returning the decoded metadata, the byte offset it was read from, the
+/// footer's checksum algorithm tag, and the format-version byte it was written
+/// with, without validating that version against [`CURRENT_FORMAT_VERSION`]
+///
+/// Used directly only by tooling (see [`read_entries_for_upgrade`]) that needs to
+/// read a table regardless of its version so it can be rewritten at the current
+/// one; every ordinary read path goes through [`parse_footer_and_meta`] instead,
+/// which refuses a version mismatch outright.
+fn parse_footer_and_meta_any_version(bytes: &[u8]) -> Result<(SSTableMeta, u64, ChecksumTag, u8)> {
+    let file_size = bytes.len() as u64;
+    if file_size < FOOTER_SIZE {
+        return Err(Error::custom("SSTable file is too small to contain a valid footer"));
+    }
+
+    let footer_start = (file_size - FOOTER_SIZE) as usize;
+    let footer = &bytes[footer_start..];
+
+    let magic_bytes = &footer[0..8];
+    if magic_bytes != MAGIC_NUMBER.to_be_bytes() {
+        return Err(Error::custom(format!(
+            "Invalid SSTable: magic number mismatch. Got: {:?}, expected: {:?}",
+            magic_bytes,
+            MAGIC_NUMBER.to_be_bytes()
+        )));
+    }
+
+    let meta_len = u64::from_le_bytes(footer[8..16].try_into().unwrap());
+    let meta_offset = u64::from_le_bytes(footer[16..24].try_into().unwrap());
+    let checksum_tag = footer[24];
+    let format_version = footer[25];
+
+    // No upper bound on `meta_len` here beyond what the `meta_end > footer_start`
+    // check below already catches: the metadata block embeds this table's min/max
+    // key in full (see `SSTableMeta`), so its size scales with however large those
+    // keys are, not just a small fixed set of scalar fields.
+    if meta_offset >= footer_start as u64 || meta_len == 0 {
+        return Err(Error::custom(format!(
+            "Invalid metadata in SSTable: offset={}, len={}",
+            meta_offset, meta_len
+        )));
+    }
+
+    let meta_start = meta_offset as usize;
+    let meta_end = meta_start + meta_len as usize;
+    if meta_end > footer_start {
+        return Err(Error::custom(format!(
+            "Metadata extends beyond footer in SSTable: offset={}, len={}, footer_start={}",
+            meta_offset, meta_len, footer_start
+        )));
+    }
+
+    let config = bincode::config::standard()
+        .with_fixed_int_encoding()
+        .with_big_endian();
+    let (meta, _): (SSTableMeta, usize) = bincode::decode_from_slice(&bytes[meta_start..meta_end], config)
+        .map_err(|e| Error::custom(format!("Failed to decode SSTable metadata: {}", e)))?;
+
+    Ok((meta, meta_offset, checksum_tag, format_version))
+}
+
+/// Parses the footer and metadata block out of a whole SSTable file's `bytes`,
+/// returning the decoded metadata, the byte offset it was read from, and the
+/// footer's checksum algorithm tag
+///
+/// Shared by every read path (`get`, `iter`, `read_filter_section`) so the
+/// footer/metadata layout only has to be validated in one place. Fails with
+/// [`Error::NotSupported`] if the table's format-version tag doesn't match
+/// [`CURRENT_FORMAT_VERSION`] -- see the CLI's `upgrade` subcommand for how to
+/// migrate a table written by an older build instead of reading it directly.
+fn parse_footer_and_meta(bytes: &[u8]) -> Result<(SSTableMeta, u64, ChecksumTag)> {
+    let (meta, meta_offset, checksum_tag, format_version) = parse_footer_and_meta_any_version(bytes)?;
+
+    if format_version != CURRENT_FORMAT_VERSION {
+        return Err(Error::NotSupported(format!(
+            "SSTable format version {format_version} isn't supported by this build (expected {CURRENT_FORMAT_VERSION}); run `upgrade` to migrate it"
+        )));
+    }
+
+    Ok((meta, meta_offset, checksum_tag))
+}
+
+/// Reads the format-version byte from the SSTable footer at `path`, without
+/// validating it against [`CURRENT_FORMAT_VERSION`]
+///
+/// Used only by the CLI's `upgrade` subcommand to tell whether a table needs
+/// migrating before [`SSTable::open`] would refuse to open it outright.
+pub(crate) fn peek_format_version(path: impl AsRef<Path>) -> Result<u8> {
+    let bytes = std::fs::read(path)?;
+    let (_, _, _, format_version) = parse_footer_and_meta_any_version(&bytes)?;
+    Ok(format_version)
+}
+
+/// Reads the compression codec and Bloom filter sizing the SSTable at `path`
+/// was configured with, regardless of its format-version tag, so the CLI's
+/// `upgrade` subcommand can recreate the table under equivalent settings
+/// instead of silently falling back to defaults
+///
+/// The exact `bits_per_key` a table was built with isn't recorded, only
+/// whether a filter block exists at all, so this returns [`DEFAULT_BITS_PER_KEY`]
+/// when one does and `0` (no filter) when one doesn't. A `compression_tag` this
+/// build doesn't recognize falls back to [`Compression::None`] rather than
+/// failing the whole migration over a cosmetic setting.
+pub(crate) fn read_settings_for_upgrade(path: impl AsRef<Path>) -> Result<(Compression, usize)> {
+    let bytes = std::fs::read(path)?;
+    let (meta, _, _, _format_version) = parse_footer_and_meta_any_version(&bytes)?;
+    let compression = Compression::from_tag(meta.compression_tag).unwrap_or(Compression::None);
+    let bits_per_key = if meta.filter_size > 0 { DEFAULT_BITS_PER_KEY } else { 0 };
+    Ok((compression, bits_per_key))
+}
+
+/// Reads every entry (including tombstones) out of the SSTable at `path`
+/// regardless of its format-version tag, for the CLI's `upgrade` subcommand to
+/// rewrite at [`CURRENT_FORMAT_VERSION`]
+///
+/// Doesn't support encrypted tables: migrating one needs the passphrase its data
+/// key was derived from, which this entry point has no way to take.
+pub(crate) fn read_entries_for_upgrade(path: impl AsRef<Path>) -> Result<Vec<(Vec<u8>, Option<Vec<u8>>)>> {
+    let bytes = std::fs::read(path)?;
+    if (bytes.len() as u64) < FOOTER_SIZE {
+        return Ok(Vec::new());
+    }
+
+    let (meta, _, checksum_tag, format_version) = parse_footer_and_meta_any_version(&bytes)?;
+    if meta.encryption_tag != encryption::TAG_NONE {
+        return Err(Error::NotSupported(
+            "migrating an encrypted SSTable isn't supported; re-create it from its source data instead"
+                .to_string(),
+        ));
+    }
+    if meta.num_entries == 0 {
+        return Ok(Vec::new());
+    }
+
+    let compression_registry = CompressorRegistry::default();
+    let encryption_registry = EncryptionRegistry::default();
+    let codec = BlockCodec {
+        compression: Compression::None,
+        compression_registry: &compression_registry,
+        encryption: EncryptionType::None,
+        encryption_registry: &encryption_registry,
+        key: None,
+    };
+
+    let index_start = meta.data_size as usize;
+    let index_end = index_start + meta.index_size as usize;
+    let index_raw = unpack_block(checksum_tag, &bytes[index_start..index_end], index_start as u64, &codec)?;
+    let index_entries = parse_index_block(&index_raw)?;
+
+    let mut entries = Vec::with_capacity(meta.num_entries as usize);
+    for (_, handle) in &index_entries {
+        let packed = &bytes[handle.offset as usize..(handle.offset + handle.size) as usize];
+        let block = unpack_block(checksum_tag, packed, handle.offset, &codec)?;
+        let (_, block_entries) = block_restarts(&block)?;
+
+        let mut cursor = block_entries;
+        let mut prev_key = Vec::new();
+        while !cursor.is_empty() {
+            // Per-entry sequence numbers were only added to the block layout in
+            // format version 3 (see `decode_block_entry_legacy`'s doc comment);
+            // a table written at an earlier version has to be decoded without
+            // one regardless of what the current build's `CURRENT_FORMAT_VERSION`
+            // is.
+            let entry = if format_version >= 3 {
+                decode_block_entry(&mut cursor, &prev_key)?
+            } else {
+                decode_block_entry_legacy(&mut cursor, &prev_key)?
+            };
+            prev_key = entry.key.clone();
+            entries.push((entry.key, entry.value));
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Number of entries between full-key "restart points" within a data block
+///
+/// Entries between restarts store only the bytes that differ from the previous
+/// key (see [`BlockBuilder`]); a restart point pays for a full key copy so `get`
+/// can binary-search to within `RESTART_INTERVAL` entries of its target instead of
+/// scanning a whole block from the start.
+const RESTART_INTERVAL: usize = 16;
+
+/// Target size, in bytes, of a data block before a new one is started
+///
+/// Blocks aren't split mid-entry, so an individual block may run a little over this.
+const BLOCK_SIZE_TARGET: usize = 4 * 1024;
+
+/// Points at a data block's byte range within the data section as stored on disk
+/// (packed: compressed, tagged and checksummed)
+#[derive(Debug, Clone, Copy)]
+struct BlockHandle {
+    offset: u64,
+    size: u64,
+}
+
+/// Appends `value` to `buf` as a LEB128-encoded unsigned varint
+fn put_varint(buf: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            buf.push(byte);
+            return;
+        }
+        buf.push(byte | 0x80);
+    }
+}
+
+/// Reads a LEB128-encoded unsigned varint off the front of `cursor`, advancing it
+fn read_varint(cursor: &mut &[u8]) -> Result<u64> {
+    let mut value = 0u64;
+    let mut shift = 0;
+    loop {
+        let Some((&byte, rest)) = cursor.split_first() else {
+            return Err(Error::custom("Unexpected end of input while reading a varint"));
+        };
+        *cursor = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+        shift += 7;
+    }
+}
+
+/// Accumulates entries into a single prefix-compressed data block
+///
+/// Each entry is encoded as `shared_len, non_shared_len, seq, value_len` (varints)
+/// followed by the non-shared key bytes and the value; `shared_len` counts how many
+/// leading bytes the key has in common with the entry before it. Every
+/// [`RESTART_INTERVAL`] entries, `shared_len` is forced to `0` so the key is written
+/// in full -- a "restart point" -- and its offset is recorded in `restarts`.
+#[derive(Default)]
+struct BlockBuilder {
+    buf: Vec<u8>,
+    restarts: Vec<u32>,
+    entries_since_restart: usize,
+    last_key: Vec<u8>,
+}
+
+impl BlockBuilder {
+    fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    fn add(&mut self, key: &[u8], seq: u64, value: Option<&[u8]>) {
+        let at_restart = self.entries_since_restart == 0;
+        let shared = if at_restart {
+            0
+        } else {
+            key.iter().zip(&self.last_key).take_while(|(a, b)| a == b).count()
+        };
+
+        if at_restart {
+            self.restarts.push(self.buf.len() as u32);
+        }
+
+        let non_shared = &key[shared..];
+        put_varint(&mut self.buf, shared as u64);
+        put_varint(&mut self.buf, non_shared.len() as u64);
+        put_varint(&mut self.buf, seq);
+        match value {
+            Some(value) => {
+                put_varint(&mut self.buf, value.len() as u64);
+                self.buf.extend_from_slice(non_shared);
+                self.buf.extend_from_slice(value);
+            }
+            None => {
+                // Tombstone: value_len encodes u64::MAX and there's no value payload.
+                put_varint(&mut self.buf, u64::MAX);
+                self.buf.extend_from_slice(non_shared);
+            }
+        }
+
+        self.last_key.clear();
+        self.last_key.extend_from_slice(key);
+        self.entries_since_restart += 1;
+        if self.entries_since_restart == RESTART_INTERVAL {
+            self.entries_since_restart = 0;
+        }
+    }
+
+    /// Like [`BlockBuilder::add`], but encodes the entry without a `seq` varint,
+    /// matching how a block was encoded before format version `3` (see
+    /// [`decode_block_entry_legacy`])
+    ///
+    /// Only used by [`write_legacy_sstable_for_test`] to build a fixture the
+    /// version-upgrade path can actually be tested against, since
+    /// [`BlockBuilder::add`] always writes the current, seq-aware layout.
+    #[cfg(test)]
+    fn add_legacy(&mut self, key: &[u8], value: Option<&[u8]>) {
+        let at_restart = self.entries_since_restart == 0;
+        let shared = if at_restart {
+            0
+        } else {
+            key.iter().zip(&self.last_key).take_while(|(a, b)| a == b).count()
+        };
+
+        if at_restart {
+            self.restarts.push(self.buf.len() as u32);
+        }
+
+        let non_shared = &key[shared..];
+        put_varint(&mut self.buf, shared as u64);
+        put_varint(&mut self.buf, non_shared.len() as u64);
+        match value {
+            Some(value) => {
+                put_varint(&mut self.buf, value.len() as u64);
+                self.buf.extend_from_slice(non_shared);
+                self.buf.extend_from_slice(value);
+            }
+            None => {
+                put_varint(&mut self.buf, u64::MAX);
+                self.buf.extend_from_slice(non_shared);
+            }
+        }
+
+        self.last_key.clear();
+        self.last_key.extend_from_slice(key);
+        self.entries_since_restart += 1;
+        if self.entries_since_restart == RESTART_INTERVAL {
+            self.entries_since_restart = 0;
+        }
+    }
+
+    /// Finishes the block, appending its restart array and their count, and
+    /// returns the raw (uncompressed, unchecksummed) block bytes
+    ///
+    /// See [`pack_block`] for compressing and checksumming the result before it's
+    /// written to disk.
+    fn finish(self) -> Vec<u8> {
+        let mut buf = self.buf;
+        for restart in &self.restarts {
+            buf.extend_from_slice(&restart.to_le_bytes());
+        }
+        buf.extend_from_slice(&(self.restarts.len() as u32).to_le_bytes());
+        buf
+    }
+}
+
+/// One entry decoded out of a data block by [`decode_block_entry`]
+struct BlockEntry {
+    key: Vec<u8>,
+    /// Sequence number of the write that produced this version of `key` (always
+    /// `0` for an entry decoded by [`decode_block_entry_legacy`], since format
+    /// versions before `3` didn't record one)
+    seq: u64,
+    value: Option<Vec<u8>>,
+}
+
+/// Decodes a single prefix-compressed entry starting at `cursor`, advancing it past
+/// the entry, and reconstructs its key from `prev_key`'s shared prefix
+fn decode_block_entry(cursor: &mut &[u8], prev_key: &[u8]) -> Result<BlockEntry> {
+    let shared = read_varint(cursor)? as usize;
+    let non_shared = read_varint(cursor)? as usize;
+    let seq = read_varint(cursor)?;
+    let value_len = read_varint(cursor)?;
+    finish_decoding_block_entry(cursor, prev_key, shared, non_shared, seq, value_len)
+}
+
+/// Like [`decode_block_entry`], but for blocks written before format version `3`
+/// added a per-entry sequence number -- used only by [`read_entries_for_upgrade`],
+/// which reads a table at whatever version it was actually written at, to migrate
+/// one written before that field existed
+fn decode_block_entry_legacy(cursor: &mut &[u8], prev_key: &[u8]) -> Result<BlockEntry> {
+    let shared = read_varint(cursor)? as usize;
+    let non_shared = read_varint(cursor)? as usize;
+    let value_len = read_varint(cursor)?;
+    finish_decoding_block_entry(cursor, prev_key, shared, non_shared, 0, value_len)
+}
+
+/// Shared tail of [`decode_block_entry`] and [`decode_block_entry_legacy`]: once
+/// the entry's length-prefixed fields have been read off `cursor`, reconstructs
+/// the key and reads the value (or tombstone) that follows
+fn finish_decoding_block_entry(
+    cursor: &mut &[u8],
+    prev_key: &[u8],
+    shared: usize,
+    non_shared: usize,
+    seq: u64,
+    value_len: u64,
+) -> Result<BlockEntry> {
+    if shared > prev_key.len() || cursor.len() < non_shared {
+        return Err(Error::custom("Corrupt data block entry"));
+    }
+
+    let mut key = Vec::with_capacity(shared + non_shared);
+    key.extend_from_slice(&prev_key[..shared]);
+    key.extend_from_slice(&cursor[..non_shared]);
+    *cursor = &cursor[non_shared..];
+
+    let value = if value_len == u64::MAX {
+        None
+    } else {
+        let value_len = value_len as usize;
+        if cursor.len() < value_len {
+            return Err(Error::custom("Corrupt data block entry"));
+        }
+        let value = cursor[..value_len].to_vec();
+        *cursor = &cursor[value_len..];
+        Some(value)
+    };
+
+    Ok(BlockEntry { key, seq, value })
+}
+
+/// Splits a finished data block into its restart offsets and the entry bytes they
+/// index into (i.e. the block minus its trailing restart array and count)
+fn block_restarts(block: &[u8]) -> Result<(Vec<u32>, &[u8])> {
+    if block.len() < 4 {
+        return Err(Error::custom("Data block is too small to contain a restart count"));
+    }
+    let count_start = block.len() - 4;
+    let count = u32::from_le_bytes(block[count_start..].try_into().unwrap()) as usize;
+
+    let restarts_start = count_start
+        .checked_sub(count * 4)
+        .ok_or_else(|| Error::custom("Data block restart array is larger than the block"))?;
+
+    let restarts = block[restarts_start..count_start]
+        .chunks_exact(4)
+        .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+        .collect();
+
+    Ok((restarts, &block[..restarts_start]))
+}
+
+/// Finds the restart point a linear scan for `key` should start from within a data
+/// block's `restarts`/`block_entries` (as split out by [`block_restarts`])
+///
+/// Restart entries always store their key in full (`shared_len == 0`), so each can
+/// be decoded on its own without reconstructing a prefix chain. A flush can carry
+/// more than [`RESTART_INTERVAL`] versions of the same key into one block (see
+/// [`crate::storage::PersistentStore::flush_sealed_memtable`]), so restart keys
+/// aren't necessarily distinct, and the key's newest versions may sit before the
+/// first restart that holds it. The scan therefore starts at the last restart whose
+/// key sorts strictly before `key` (or at restart `0`), never at a restart holding
+/// `key` itself, so it can't skip past the key's newer versions.
+fn restart_index_for_key(restarts: &[u32], block_entries: &[u8], key: &[u8]) -> Result<usize> {
+    let restart_key_at = |idx: usize| -> Result<Vec<u8>> {
+        let mut cursor = &block_entries[restarts[idx] as usize..];
+        Ok(decode_block_entry(&mut cursor, &[])?.key)
+    };
+
+    // Lower-bound: the first restart whose key is >= `key`.
+    let mut lo = 0usize;
+    let mut hi = restarts.len();
+    while lo < hi {
+        let mid = (lo + hi) / 2;
+        if restart_key_at(mid)?.as_slice() < key {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+
+    Ok(lo.saturating_sub(1))
+}
+
+/// Parses the index block into `(block's last key, block handle)` pairs, sorted by key
+fn parse_index_block(mut cursor: &[u8]) -> Result<Vec<(Vec<u8>, BlockHandle)>> {
+    let mut entries = Vec::new();
+    while !cursor.is_empty() {
+        let mut key_len_buf = [0u8; 8];
+        cursor.read_exact(&mut key_len_buf)?;
+        let key_len = u64::from_le_bytes(key_len_buf) as usize;
+
+        let mut key = vec![0u8; key_len];
+        cursor.read_exact(&mut key)?;
+
+        let mut offset_buf = [0u8; 8];
+        cursor.read_exact(&mut offset_buf)?;
+        let offset = u64::from_le_bytes(offset_buf);
+
+        let mut size_buf = [0u8; 8];
+        cursor.read_exact(&mut size_buf)?;
+        let size = u64::from_le_bytes(size_buf);
+
+        entries.push((key, BlockHandle { offset, size }));
+    }
+    Ok(entries)
+}
+
+/// Compresses a raw block (data or index), optionally seals it under an
+/// encryption key, and appends its on-disk trailer, per `codec`'s settings
+///
+/// The output is, in order: the compressed bytes followed by a one-byte
+/// compression tag (the two sealed together if encryption is enabled), then a
+/// one-byte encryption tag (written either way), then a CRC-32 checksum covering
+/// everything written so far (see
+/// [`crate::storage::checksum`]). Mirrors how LevelDB-style tables record a
+/// block's codec alongside its checksum.
+///
+/// The result is self-describing: [`unpack_block`] needs nothing but a matching
+/// `codec` (and, if the block is encrypted, the right key) to read it back,
+/// regardless of what the table is currently configured to write new blocks with.
+fn pack_block(raw: &[u8], codec: &BlockCodec) -> Result<Vec<u8>> {
+    let mut packed = codec.compression_registry.compress(codec.compression, raw)?;
+    packed.push(codec.compression.tag());
+
+    if codec.encryption != EncryptionType::None {
+        let key = codec
+            .key
+            .ok_or_else(|| Error::custom("encryption is enabled but this table has no data key"))?;
+        packed = codec.encryption_registry.seal(codec.encryption, key, &packed)?;
+    }
+    packed.push(codec.encryption.tag());
+
+    checksum::append_checksum(&mut packed);
+    Ok(packed)
+}
+
+/// Returns a copy of `codec` with compression disabled, keeping its encryption
+/// settings intact
+///
+/// Used to pack the index block: it's read on every lookup to find the one data
+/// block that might hold a key, so leaving it uncompressed trades a little disk
+/// space for never having to decompress it first. It's still tagged, checksummed,
+/// and (if the table is configured for it) encrypted exactly like a data block --
+/// only the codec differs.
+fn index_codec<'a>(codec: &BlockCodec<'a>) -> BlockCodec<'a> {
+    BlockCodec {
+        compression: Compression::None,
+        ..*codec
+    }
+}
+
+/// Reverses [`pack_block`]: verifies `packed`'s checksum, opens it under `codec`'s
+/// key if its trailing encryption tag says it's sealed, then decompresses it with
+/// whatever codec its trailing compression tag names
+///
+/// `offset` should be `packed`'s own byte offset within the SSTable file, used to
+/// make a checksum mismatch's [`Error::Corruption`] (or an authentication
+/// failure's [`Error::AuthenticationFailed`]) actionable.
+fn unpack_block(checksum_tag: ChecksumTag, packed: &[u8], offset: u64, codec: &BlockCodec) -> Result<Vec<u8>> {
+    let body = checksum::verify_checksum(checksum_tag, packed, offset)?;
+    let Some((&encryption_tag, sealed)) = body.split_last() else {
+        return Err(Error::custom("Packed block is too small to contain an encryption tag"));
+    };
+
+    let compressed = if encryption_tag == encryption::TAG_NONE {
+        sealed.to_vec()
+    } else {
+        let key = codec
+            .key
+            .ok_or_else(|| Error::custom("block is encrypted but this table has no data key"))?;
+        codec.encryption_registry.open(encryption_tag, key, sealed, offset)?
+    };
+
+    let Some((&compression_tag, compressed)) = compressed.split_last() else {
+        return Err(Error::custom("Packed block is too small to contain a compression tag"));
+    };
+    codec.compression_registry.decompress(compression_tag, compressed)
+}
+
+/// Writes an uncompressed, unencrypted SSTable at `path` whose blocks are encoded
+/// without a per-entry `seq` (i.e. as [`decode_block_entry_legacy`] expects) and
+/// whose footer is tagged with `format_version`, to give [`read_entries_for_upgrade`]
+/// and the version-upgrade path something genuine to migrate in tests
+///
+/// Only [`SSTable::write_batch`] can produce a real SSTable file, and it always
+/// writes the current, seq-aware block layout -- there's no way to get it to emit
+/// the pre-version-3 layout a truly old table would have, which is what a test of
+/// the upgrade path actually needs to exercise.
+#[cfg(test)]
+pub(crate) fn write_legacy_sstable_for_test(
+    path: impl AsRef<Path>,
+    entries: &[(Vec<u8>, Option<Vec<u8>>)],
+    format_version: u8,
+) -> Result<()> {
+    let mut builder = BlockBuilder::default();
+    for (key, maybe_value) in entries {
+        builder.add_legacy(key, maybe_value.as_deref());
+    }
+    let raw = builder.finish();
+    let compression_registry = CompressorRegistry::default();
+    let encryption_registry = EncryptionRegistry::default();
+    let codec = BlockCodec {
+        compression: Compression::None,
+        compression_registry: &compression_registry,
+        encryption: EncryptionType::None,
+        encryption_registry: &encryption_registry,
+        key: None,
+    };
+    let packed = pack_block(&raw, &codec)?;
+
+    let last_key = entries.last().map(|(k, _)| k.clone()).unwrap_or_default();
+    let mut index_raw = Vec::new();
+    index_raw.extend_from_slice(&(last_key.len() as u64).to_le_bytes());
+    index_raw.extend_from_slice(&last_key);
+    index_raw.extend_from_slice(&0u64.to_le_bytes());
+    index_raw.extend_from_slice(&(packed.len() as u64).to_le_bytes());
+    let index_buf = pack_block(&index_raw, &index_codec(&codec))?;
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(&packed)?;
+    writer.flush()?;
+    let index_start = writer.stream_position()?;
+    writer.write_all(&index_buf)?;
+    writer.flush()?;
+    let index_size = index_buf.len() as u64;
+
+    let min_key = entries.first().map(|(k, _)| k.clone()).unwrap_or_default();
+    let max_key = entries.last().map(|(k, _)| k.clone()).unwrap_or_default();
+    write_footer(
+        &mut writer,
+        entries.len() as u64,
+        index_start,
+        index_size,
+        0,
+        Compression::None.tag(),
+        EncryptionType::None.tag(),
+        [0u8; encryption::SALT_LEN],
+        min_key,
+        max_key,
+        format_version,
+    )?;
+    writer.flush()?;
+
+    Ok(())
+}
+
+/// Writes the metadata block and footer (magic number, metadata location,
+/// checksum algorithm tag, and format version) that trail every SSTable's
+/// data/index/filter sections
+///
+/// Shared by [`SSTable::write_batch`] and [`SSTableBuilder::finish`] so the footer
+/// layout only has to be assembled in one place.
+#[allow(clippy::too_many_arguments)]
+fn write_footer(
+    writer: &mut (impl Write + Seek),
+    num_entries: u64,
     data_size: u64,
-    /// Size of the index section in bytes
     index_size: u64,
+    filter_size: u64,
+    compression_tag: CompressionTag,
+    encryption_tag: EncryptionTag,
+    salt: [u8; encryption::SALT_LEN],
+    min_key: Vec<u8>,
+    max_key: Vec<u8>,
+    format_version: u8,
+) -> Result<()> {
+    let meta = SSTableMeta {
+        num_entries,
+        data_size,
+        index_size,
+        filter_size,
+        compression_tag,
+        encryption_tag,
+        salt,
+        min_key,
+        max_key,
+    };
+
+    let config = bincode::config::standard()
+        .with_fixed_int_encoding()
+        .with_big_endian();
+    let meta_bytes = bincode::encode_to_vec(&meta, config)
+        .map_err(|e| Error::custom(format!("Failed to encode SSTable metadata: {}", e)))?;
+
+    let meta_start = writer.stream_position()?;
+    writer.write_all(&meta_bytes)?;
+
+    writer.write_all(&MAGIC_NUMBER.to_be_bytes())?;
+    writer.write_all(&(meta_bytes.len() as u64).to_le_bytes())?;
+    writer.write_all(&meta_start.to_le_bytes())?;
+    writer.write_all(&[checksum::CHECKSUM_CRC32])?;
+    writer.write_all(&[format_version])?;
+
+    Ok(())
 }
 
 impl SSTable {
@@ -90,6 +964,52 @@ impl SSTable {
     /// let sstable = SSTable::create("path/to/sstable").unwrap();
     /// ```
     pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        Self::create_with_compression(path, Compression::None)
+    }
+
+    /// Creates a new SSTable at the given path, compressing its data section with
+    /// `compression` once [`SSTable::write_batch`] is called
+    ///
+    /// # Example
+    /// ```no_run
+    /// use rocksdb_clone::config::Compression;
+    /// use rocksdb_clone::storage::SSTable;
+    ///
+    /// let sstable = SSTable::create_with_compression("path/to/sstable", Compression::None).unwrap();
+    /// ```
+    pub fn create_with_compression(path: impl AsRef<Path>, compression: Compression) -> Result<Self> {
+        Self::create_with_options(path, compression, DEFAULT_BITS_PER_KEY, false)
+    }
+
+    /// Creates a new SSTable at the given path, compressing its data section with
+    /// `compression`, budgeting `bits_per_key` bits of Bloom filter per key (`0`
+    /// disables the filter), and reading it back via memory mapping when
+    /// `use_mmap` is set, once [`SSTable::write_batch`] is called
+    pub fn create_with_options(
+        path: impl AsRef<Path>,
+        compression: Compression,
+        bits_per_key: usize,
+        use_mmap: bool,
+    ) -> Result<Self> {
+        Self::create_with_encryption(path, compression, bits_per_key, use_mmap, EncryptionType::None, None)
+    }
+
+    /// Creates a new SSTable at the given path, additionally sealing its data and
+    /// index blocks with `encryption` once [`SSTable::write_batch`] is called
+    ///
+    /// A fresh random salt is generated and `passphrase` is stretched into this
+    /// table's data key via [`encryption::derive_key`]; the salt is recorded in the
+    /// table's metadata so [`SSTable::open_with_encryption`] can re-derive the same
+    /// key later. `passphrase` must be `Some` unless `encryption` is
+    /// [`EncryptionType::None`].
+    pub fn create_with_encryption(
+        path: impl AsRef<Path>,
+        compression: Compression,
+        bits_per_key: usize,
+        use_mmap: bool,
+        encryption: EncryptionType,
+        passphrase: Option<&[u8]>,
+    ) -> Result<Self> {
         let path = path.as_ref();
         log::debug!("Creating new SSTable at: {}", path.display());
 
@@ -97,12 +1017,21 @@ impl SSTable {
             return Err(Error::custom("File already exists"));
         }
 
+        let (salt, key) = if encryption == EncryptionType::None {
+            ([0u8; encryption::SALT_LEN], None)
+        } else {
+            let passphrase = passphrase
+                .ok_or_else(|| Error::InvalidArgument("a passphrase is required to create an encrypted SSTable".into()))?;
+            let salt = encryption::generate_salt();
+            (salt, Some(encryption::derive_key(passphrase, &salt)?))
+        };
+
         let file = OpenOptions::new()
             .create(true)
             .truncate(true)
             .read(true)
             .write(true)
-            .open(&path)
+            .open(path)
             .map_err(|e| {
                 let msg = format!("Failed to create SSTable file at {}: {}", path.display(), e);
                 log::error!("{}", msg);
@@ -112,9 +1041,31 @@ impl SSTable {
         Ok(Self {
             file,
             path: path.to_path_buf(),
+            compression,
+            registry: CompressorRegistry::default(),
+            filter_policy: bloom::policy_for(bits_per_key),
+            filter: Vec::new(),
+            use_mmap,
+            mmap: None,
+            encryption,
+            key,
+            salt,
+            encryption_registry: EncryptionRegistry::default(),
         })
     }
 
+    /// Bundles this table's compression and encryption settings into a
+    /// [`BlockCodec`] for [`pack_block`]/[`unpack_block`]
+    fn block_codec(&self) -> BlockCodec<'_> {
+        BlockCodec {
+            compression: self.compression,
+            compression_registry: &self.registry,
+            encryption: self.encryption,
+            encryption_registry: &self.encryption_registry,
+            key: self.key.as_ref(),
+        }
+    }
+
     /// Returns the path to this SSTable file
     ///
     /// # Returns
@@ -131,6 +1082,25 @@ impl SSTable {
         &self.path
     }
 
+    /// Returns this table's smallest and largest key, or `None` if it holds no
+    /// entries
+    ///
+    /// Used by leveled compaction to tell whether two tables' key ranges
+    /// overlap without reading either one's data section.
+    pub(crate) fn key_range(&self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        let bytes = self.file_bytes()?;
+        if (bytes.len() as u64) < FOOTER_SIZE {
+            return Ok(None);
+        }
+
+        let (meta, _, _) = parse_footer_and_meta(&bytes)?;
+        if meta.num_entries == 0 {
+            return Ok(None);
+        }
+
+        Ok(Some((meta.min_key, meta.max_key)))
+    }
+
     /// Opens an existing SSTable for reading
     ///
     /// # Arguments
@@ -148,23 +1118,185 @@ impl SSTable {
     /// let sstable = SSTable::open("path/to/existing/sstable").unwrap();
     /// ```
     pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::open_with_options(path, CompressorRegistry::default(), false)
+    }
+
+    /// Opens an existing SSTable for reading, decompressing its data section with
+    /// `registry` instead of the built-in codecs
+    ///
+    /// Use this when the table may have been written with a codec that isn't part of
+    /// this build's default registry, e.g. a custom compressor identified by a
+    /// non-standard tag byte.
+    pub fn open_with_registry(path: impl AsRef<Path>, registry: CompressorRegistry) -> Result<Self> {
+        Self::open_with_options(path, registry, false)
+    }
+
+    /// Opens an existing SSTable for reading, decompressing its data section with
+    /// `registry` and reading its file via memory mapping when `use_mmap` is set
+    ///
+    /// Fails with [`Error::InvalidArgument`] if the table was written with
+    /// encryption enabled; use [`SSTable::open_with_encryption`] for those.
+    pub fn open_with_options(
+        path: impl AsRef<Path>,
+        registry: CompressorRegistry,
+        use_mmap: bool,
+    ) -> Result<Self> {
+        Self::open_with_encryption(path, registry, use_mmap, None)
+    }
+
+    /// Opens an existing SSTable for reading, decompressing its data section with
+    /// `registry`, reading its file via memory mapping when `use_mmap` is set, and
+    /// re-deriving its data key from `passphrase` plus the salt recorded in its
+    /// metadata if it was written with encryption enabled
+    ///
+    /// `passphrase` must be `Some` if (and only if) the table is encrypted; a
+    /// mismatch in either direction fails with [`Error::InvalidArgument`]. A wrong
+    /// passphrase isn't caught here -- it surfaces as
+    /// [`Error::AuthenticationFailed`] the first time a block is actually read.
+    pub fn open_with_encryption(
+        path: impl AsRef<Path>,
+        registry: CompressorRegistry,
+        use_mmap: bool,
+        passphrase: Option<&[u8]>,
+    ) -> Result<Self> {
         let path = path.as_ref();
         let file = OpenOptions::new().read(true).open(path)?;
-        let sstable = Self {
+        let mut sstable = Self {
             file,
             path: path.to_path_buf(),
+            compression: Compression::None,
+            registry,
+            // Irrelevant for reads: `may_contain` reads everything it needs (including
+            // the hash count) from the filter bytes themselves. Only matters again if
+            // this table is ever written to via `write_batch`.
+            filter_policy: bloom::policy_for(DEFAULT_BITS_PER_KEY),
+            filter: Vec::new(),
+            use_mmap,
+            mmap: None,
+            encryption: EncryptionType::None,
+            key: None,
+            salt: [0u8; encryption::SALT_LEN],
+            encryption_registry: EncryptionRegistry::default(),
         };
 
         sstable.verify_metadata()?;
+        sstable.verify_index_checksum()?;
+
+        let (meta, _, _) = parse_footer_and_meta(&sstable.file_bytes()?)?;
+        let is_encrypted = meta.encryption_tag != encryption::TAG_NONE;
+        match (is_encrypted, passphrase) {
+            (false, None) => {}
+            (false, Some(_)) => {
+                return Err(Error::InvalidArgument(
+                    "a passphrase was given but this SSTable isn't encrypted".to_string(),
+                ))
+            }
+            (true, None) => {
+                return Err(Error::InvalidArgument(
+                    "this SSTable is encrypted and requires a passphrase to open".to_string(),
+                ))
+            }
+            (true, Some(passphrase)) => {
+                sstable.salt = meta.salt;
+                sstable.key = Some(encryption::derive_key(passphrase, &meta.salt)?);
+            }
+        }
+
+        sstable.refresh_mmap();
+        sstable.filter = sstable.read_filter_section()?;
 
         Ok(sstable)
     }
 
+    /// (Re-)establishes this table's memory mapping, if `use_mmap` is set and the
+    /// `mmap` feature is enabled
+    ///
+    /// Called once at open and again after every [`SSTable::write_batch`], since
+    /// that call truncates and rewrites the file out from under any prior mapping.
+    #[cfg(feature = "mmap")]
+    fn refresh_mmap(&mut self) {
+        self.mmap = if self.use_mmap {
+            // SAFETY: a table's file is immutable once a `write_batch` call
+            // returns, and compaction never rewrites a path in place -- it
+            // always writes to a fresh file and only removes the old one once
+            // the new one is durable -- so nothing else in this process
+            // truncates or appends to a mapped file behind our back.
+            unsafe { memmap2::Mmap::map(&self.file).ok() }
+        } else {
+            None
+        };
+    }
+
+    #[cfg(not(feature = "mmap"))]
+    fn refresh_mmap(&mut self) {}
+
+    /// Returns this table's memory-mapped bytes, if `use_mmap` is set, the `mmap`
+    /// feature is enabled, and the mapping succeeded
+    #[cfg(feature = "mmap")]
+    fn mapped_bytes(&self) -> Option<&[u8]> {
+        self.mmap.as_deref()
+    }
+
+    #[cfg(not(feature = "mmap"))]
+    fn mapped_bytes(&self) -> Option<&[u8]> {
+        debug_assert!(self.mmap.is_none(), "mmap field should stay None without the `mmap` feature");
+        None
+    }
+
+    /// Returns this table's full file contents, backed by the memory-mapped region
+    /// when available and falling back to an ordinary buffered read otherwise
+    fn file_bytes(&self) -> Result<Cow<'_, [u8]>> {
+        if let Some(bytes) = self.mapped_bytes() {
+            return Ok(Cow::Borrowed(bytes));
+        }
+        Ok(Cow::Owned(std::fs::read(&self.path)?))
+    }
+
+    /// Reads this table's filter block from disk, called once at [`SSTable::open`]
+    fn read_filter_section(&self) -> Result<Vec<u8>> {
+        let bytes = self.file_bytes()?;
+        if (bytes.len() as u64) < FOOTER_SIZE {
+            return Ok(Vec::new());
+        }
+
+        let (meta, _, _) = parse_footer_and_meta(&bytes)?;
+        if meta.filter_size == 0 {
+            return Ok(Vec::new());
+        }
+
+        let filter_start = (meta.data_size + meta.index_size) as usize;
+        let filter_end = filter_start + meta.filter_size as usize;
+        Ok(bytes[filter_start..filter_end].to_vec())
+    }
+
+    /// Verifies the index section's checksum, called once at [`SSTable::open`] so
+    /// corruption there is caught immediately rather than waiting for the first
+    /// `get` or `iter` call that touches it
+    fn verify_index_checksum(&self) -> Result<()> {
+        let bytes = self.file_bytes()?;
+        if (bytes.len() as u64) < FOOTER_SIZE {
+            return Ok(());
+        }
+
+        let (meta, meta_offset, checksum_tag) = parse_footer_and_meta(&bytes)?;
+        let index_start = meta.data_size as usize;
+        let index_end = index_start + meta.index_size as usize;
+        if index_end as u64 > meta_offset {
+            return Err(Error::custom(format!(
+                "Index section extends beyond metadata in SSTable: start={}, end={}, meta_offset={}",
+                index_start, index_end, meta_offset
+            )));
+        }
+
+        checksum::verify_checksum(checksum_tag, &bytes[index_start..index_end], index_start as u64)?;
+        Ok(())
+    }
+
     /// Verifies that the SSTable file has valid metadata
     fn verify_metadata(&self) -> Result<()> {
-        // The footer is the last 24 bytes of the file
-        // It contains: [magic_number (8)][meta_len (8)][meta_offset (8)]
-        const FOOTER_SIZE: u64 = 24; // 8 (magic) + 8 (meta_len) + 8 (meta_offset)
+        // The footer is the last 26 bytes of the file
+        // It contains: [magic_number (8)][meta_len (8)][meta_offset (8)][checksum_tag (1)][format_version (1)]
+        const FOOTER_SIZE: u64 = 26; // 8 (magic) + 8 (meta_len) + 8 (meta_offset) + 1 (checksum tag) + 1 (format version)
 
         let file_size = self.file.metadata()?.len();
         if file_size < FOOTER_SIZE {
@@ -199,8 +1331,11 @@ impl SSTable {
             crate::error::Error::custom("Failed to parse metadata length from footer")
         })?);
 
-        // Verify the metadata length is reasonable
-        if meta_len == 0 || meta_len > 1024 {
+        // No upper bound beyond what the "extends beyond footer" check below
+        // already catches: the metadata block embeds this table's min/max key in
+        // full (see `SSTableMeta`), so its size scales with however large those
+        // keys are.
+        if meta_len == 0 {
             return Err(crate::error::Error::custom(format!(
                 "Invalid metadata length in SSTable: {} (0x{:x})",
                 meta_len, meta_len
@@ -238,6 +1373,24 @@ impl SSTable {
             file.read_exact(&mut meta_buf)?;
         }
 
+        // Byte 24 is the checksum algorithm tag
+        let checksum_tag = footer[24];
+        if checksum_tag != checksum::CHECKSUM_CRC32 {
+            return Err(crate::error::Error::custom(format!(
+                "Unknown checksum algorithm tag in SSTable footer: {}",
+                checksum_tag
+            )));
+        }
+
+        // Byte 25 is the format version; a table written by a build that isn't this
+        // one's exact version is left alone rather than risk silently misreading it.
+        let format_version = footer[25];
+        if format_version != CURRENT_FORMAT_VERSION {
+            return Err(crate::error::Error::NotSupported(format!(
+                "SSTable format version {format_version} isn't supported by this build (expected {CURRENT_FORMAT_VERSION}); run `upgrade` to migrate it"
+            )));
+        }
+
         Ok(())
     }
 
@@ -245,9 +1398,15 @@ impl SSTable {
     ///
     /// This method will completely overwrite any existing data in the SSTable.
     /// Tombstones (deletions) are represented by `None` values in the input.
+    /// `entries` may hold more than one version of the same key (see
+    /// [`crate::storage::PersistentStore::flush_sealed_memtable`]), as long as
+    /// they're ordered the same way [`crate::storage::MemTable`] orders them:
+    /// ascending by key, then descending by sequence number, so the newest
+    /// version of a key comes first.
     ///
     /// # Arguments
-    /// * `entries` - Slice of key-value pairs where the value is an Option:
+    /// * `entries` - Slice of `(key, seq, value)` triples, sorted as described
+    ///   above, where `value` is:
     ///   - `Some(Vec<u8>)`: A regular key-value pair
     ///   - `None`: A tombstone (deletion marker)
     ///
@@ -260,11 +1419,64 @@ impl SSTable {
     /// # let mut sstable = SSTable::create("path/to/sstable").unwrap();
     /// // Write some data
     /// sstable.write_batch(&[
-    ///     (b"key1".to_vec(), Some(b"value1".to_vec())),
-    ///     (b"key2".to_vec(), None),  // Tombstone
+    ///     (b"key1".to_vec(), 1, Some(b"value1".to_vec())),
+    ///     (b"key2".to_vec(), 2, None),  // Tombstone
     /// ]).unwrap();
     /// ```
-    pub fn write_batch(&mut self, entries: &[(Vec<u8>, Option<Vec<u8>>)]) -> Result<()> {
+    pub fn write_batch(&mut self, entries: &[(Vec<u8>, u64, Option<Vec<u8>>)]) -> Result<()> {
+        // Build the data section in memory first, as a sequence of prefix-compressed
+        // blocks (see `BlockBuilder`), each individually compressed, tagged, and
+        // checksummed by `pack_block` before it's appended -- so `get` only ever has
+        // to decompress the one block it actually needs, not the whole section.
+        let codec = self.block_codec();
+        let mut data = Vec::new();
+        let mut index_entries: Vec<(Vec<u8>, BlockHandle)> = Vec::new();
+        let mut builder = BlockBuilder::default();
+
+        for (key, seq, maybe_value) in entries {
+            builder.add(key, *seq, maybe_value.as_deref());
+
+            if builder.buf.len() >= BLOCK_SIZE_TARGET {
+                let raw = std::mem::take(&mut builder).finish();
+                let packed = pack_block(&raw, &codec)?;
+                let offset = data.len() as u64;
+                let size = packed.len() as u64;
+                data.extend_from_slice(&packed);
+                index_entries.push((key.clone(), BlockHandle { offset, size }));
+            }
+        }
+
+        if !builder.is_empty() {
+            // Safe to unwrap: a non-empty builder means at least one entry was added.
+            let last_key = entries.last().map(|(k, _, _)| k.clone()).unwrap();
+            let raw = builder.finish();
+            let packed = pack_block(&raw, &codec)?;
+            let offset = data.len() as u64;
+            let size = packed.len() as u64;
+            data.extend_from_slice(&packed);
+            index_entries.push((last_key, BlockHandle { offset, size }));
+        }
+
+        let keys: Vec<&[u8]> = entries.iter().map(|(k, _, _)| k.as_slice()).collect();
+        let filter = self.filter_policy.build(&keys);
+
+        // Build the index block's raw bytes: for each data block, its last key (so
+        // `get` can binary-search for the candidate block) followed by its handle;
+        // then pack it the same way a data block is packed. Done before the file is
+        // touched below so `codec`'s borrow of `self` doesn't overlap the mutable
+        // borrow of `self.file` that the write-out needs.
+        let mut index_raw = Vec::new();
+        for (last_key, handle) in &index_entries {
+            index_raw.extend_from_slice(&(last_key.len() as u64).to_le_bytes());
+            index_raw.extend_from_slice(last_key);
+            index_raw.extend_from_slice(&handle.offset.to_le_bytes());
+            index_raw.extend_from_slice(&handle.size.to_le_bytes());
+        }
+        // Packed uncompressed (see `index_codec`): the index is read on every
+        // lookup, so paying decompression cost there would undercut the whole
+        // point of seeking via it instead of scanning the data section.
+        let index_buf = pack_block(&index_raw, &index_codec(&codec))?;
+
         // Create a new BufWriter for the file
         let file = &mut self.file;
         file.seek(SeekFrom::Start(0))?; // Start from beginning of file
@@ -272,166 +1484,61 @@ impl SSTable {
 
         let mut writer = BufWriter::new(file);
 
-        // Write data section
-        let data_start = 0; // Start at beginning of file
-        let mut current_offset = data_start;
-
-        // First, collect all entries with their offsets
-        let mut index_entries = Vec::new();
-
-        // Write all key-value pairs and record their offsets
-        for (key, maybe_value) in entries {
-            // Record the current position before writing the key
-            let entry_offset = current_offset;
-
-            // Calculate the size of the key header (8 bytes for key length)
-            let key_header_size = 8;
-
-            // Write key length (8 bytes) + key
-            writer.write_all(&(key.len() as u64).to_le_bytes())?;
-            writer.write_all(key)?;
-
-            // For None (tombstone), write u64::MAX as the length
-            // For Some(value), write the actual value length
-            match maybe_value {
-                Some(value) => {
-                    // Write value length + value
-                    writer.write_all(&(value.len() as u64).to_le_bytes())?;
-                    writer.write_all(value)?;
-
-                    // Update current offset: key header + key + value header + value
-                    current_offset +=
-                        key_header_size as u64 + key.len() as u64 + 8 + value.len() as u64;
-                }
-                None => {
-                    // Tombstone: write u64::MAX as length and no value
-                    let tombstone_marker = u64::MAX;
-                    writer.write_all(&tombstone_marker.to_le_bytes())?;
-
-                    // Update current offset: key header + key + tombstone marker (8 bytes)
-                    current_offset += key_header_size as u64 + key.len() as u64 + 8;
-                }
-            }
-
-            // Add the entry to the index with the correct offset
-            // For tombstones, we still need to add them to the index so they can override previous values
-            index_entries.push((key.clone(), entry_offset));
-        }
-
-        // Make sure all data is written to the underlying file
+        // Write the data section: already a sequence of independently packed blocks
+        writer.write_all(&data)?;
         writer.flush()?;
 
         // Get the current position for the start of the index section
-        let index_start = current_offset;
-
-        // Write index entries
-        for (key, offset) in &index_entries {
-            // Write key length (8 bytes) + key + offset (8 bytes)
-            writer.write_all(&(key.len() as u64).to_le_bytes())?;
-            writer.write_all(key)?;
-            writer.write_all(&offset.to_le_bytes())?;
-        }
+        let index_start = writer.stream_position()?;
 
-        // Make sure all index entries are written
+        writer.write_all(&index_buf)?;
         writer.flush()?;
+        let index_size = index_buf.len() as u64;
 
-        // Get the current position for the end of the index section
-        let index_end = writer.stream_position()?;
-        let index_size = index_end - index_start;
+        // Write the filter section right after the index
+        let index_end = index_start + index_size;
+        writer.write_all(&filter)?;
+        writer.flush()?;
+        let filter_end = writer.stream_position()?;
+        let filter_size = filter_end - index_end;
 
-        // Calculate data size (from start of file to start of index)
+        // Data size on disk is the packed blocks' total length (where the index starts)
         let data_size = index_start;
 
-        // Write footer with metadata
-        let meta = SSTableMeta {
-            num_entries: entries.len() as u64,
+        // `compression_tag`/`encryption_tag` record this table's configured
+        // defaults so a reader doesn't need external configuration to know them --
+        // every block is independently tagged too, so neither is load-bearing for
+        // decoding any particular block.
+        let min_key = entries.first().map(|(k, _, _)| k.clone()).unwrap_or_default();
+        let max_key = entries.last().map(|(k, _, _)| k.clone()).unwrap_or_default();
+        write_footer(
+            &mut writer,
+            entries.len() as u64,
             data_size,
             index_size,
-        };
-
-        // Serialize metadata to a buffer with fixed-size encoding
-        let config = bincode::config::standard()
-            .with_fixed_int_encoding()
-            .with_big_endian();
-
-        let meta_bytes = bincode::encode_to_vec(&meta, config).map_err(|e| {
-            io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!("Failed to encode SSTable metadata: {}", e),
-            )
-        })?;
-
-        // Calculate the footer position and write metadata length (8 bytes)
-        let meta_len = meta_bytes.len() as u64;
-        let meta_len_bytes = meta_len.to_le_bytes();
-
-        // First, write the data and index blocks
-        // The footer will be written after the metadata
-
-        // Get the current position where we'll write the metadata
-        let meta_start = writer.stream_position()?;
-
-        // Write metadata first
-        writer.write_all(&meta_bytes)?;
-        log::debug!(
-            "Wrote metadata bytes ({}): {:?}",
-            meta_bytes.len(),
-            &meta_bytes
-        );
-
-        // Then write the footer at the end of the file
-        // Footer structure: [magic_number (8)][meta_len (8)][meta_offset (8)]
-        let magic_bytes = MAGIC_NUMBER.to_be_bytes();
-
-        // Write magic number (8 bytes)
-        writer.write_all(&magic_bytes)?;
-
-        // Write metadata length (8 bytes)
-        writer.write_all(&meta_len_bytes)?;
-
-        // Write metadata start offset (8 bytes)
-        let meta_start_bytes = meta_start.to_le_bytes();
-        writer.write_all(&meta_start_bytes)?;
-
-        // Log the exact bytes being written to the footer
-        let mut footer = Vec::new();
-        footer.extend_from_slice(&magic_bytes);
-        footer.extend_from_slice(&meta_len_bytes);
-        footer.extend_from_slice(&meta_start_bytes);
-
-        // Verify the footer can be read back correctly
-        let read_magic = &footer[0..8];
-        let read_meta_len = u64::from_le_bytes(footer[8..16].try_into().unwrap());
-        let read_meta_offset = u64::from_le_bytes(footer[16..24].try_into().unwrap());
-
-        if read_magic != MAGIC_NUMBER.to_be_bytes() {
-            return Err(crate::error::Error::custom(format!(
-                "Footer verification failed: invalid magic number: {:?}",
-                read_magic
-            )));
-        }
-
-        if read_meta_len != meta_len {
-            return Err(crate::error::Error::custom(format!(
-                "Footer verification failed: expected meta_len={}, got {}",
-                meta_len, read_meta_len
-            )));
-        }
-
-        if read_meta_offset != meta_start {
-            return Err(crate::error::Error::custom(format!(
-                "Footer verification failed: expected meta_offset={}, got {}",
-                meta_start, read_meta_offset
-            )));
-        }
+            filter_size,
+            self.compression.tag(),
+            self.encryption.tag(),
+            self.salt,
+            min_key,
+            max_key,
+            CURRENT_FORMAT_VERSION,
+        )?;
 
         // Ensure everything is written to disk
         writer.flush()?;
+        // Drop the writer (and its borrow of `self.file`) before `refresh_mmap`
+        // needs `&mut self`.
+        drop(writer);
+
+        self.filter = filter;
+        self.refresh_mmap();
 
         Ok(())
     }
 
-    /// Looks up a key in the SSTable
+    /// Looks up a key in the SSTable, collapsing a tombstone and a genuine miss
+    /// into the same `Ok(None)`
     ///
     /// # Arguments
     /// * `key` - The key to look up
@@ -441,11 +1548,16 @@ impl SSTable {
     /// - `Ok(None)` if the key has a tombstone or doesn't exist
     /// - `Err(_)` if there was an error reading the SSTable
     ///
+    /// A caller merging several SSTables (like [`PersistentStore`](crate::storage::PersistentStore))
+    /// needs to tell those two cases apart -- a tombstone shadows every older version of
+    /// the key, but a miss in this table says nothing and the search must continue into
+    /// older tables -- so it should use [`SSTable::get_raw`] instead.
+    ///
     /// # Example
     /// ```no_run
     /// # use rocksdb_clone::storage::SSTable;
     /// # let mut sstable = SSTable::create("path/to/sstable").unwrap();
-    /// # sstable.write_batch(&[(b"key1".to_vec(), Some(b"value1".to_vec()))]).unwrap();
+    /// # sstable.write_batch(&[(b"key1".to_vec(), 1, Some(b"value1".to_vec()))]).unwrap();
     /// // Look up a key
     /// if let Some(value) = sstable.get(b"key1").unwrap() {
     ///     println!("Found value: {:?}", value);
@@ -454,193 +1566,758 @@ impl SSTable {
     /// }
     /// ```
     pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
-        let mut file = BufReader::new(File::open(&self.path)?);
+        Ok(self.get_raw(key)?.flatten())
+    }
 
-        // Get file size
-        let file_size = file.seek(SeekFrom::End(0))?;
+    /// Looks up a key in the SSTable, distinguishing a tombstone from a genuine miss
+    ///
+    /// Returns `Ok(Some(Some(value)))` if the key has a value, `Ok(Some(None))` if
+    /// it's tombstoned, and `Ok(None)` if the table holds nothing for this key at
+    /// all. Used by [`PersistentStore::get`](crate::storage::PersistentStore::get)
+    /// to fall through to older tables on a miss while still stopping at a
+    /// tombstone. Equivalent to [`SSTable::get_raw_at`] with `seq = u64::MAX`, i.e.
+    /// whatever's newest.
+    pub(crate) fn get_raw(&self, key: &[u8]) -> Result<Option<Option<Vec<u8>>>> {
+        self.get_raw_at(key, u64::MAX)
+    }
 
-        if file_size < FOOTER_SIZE {
+    /// Like [`SSTable::get_raw`], but only considers versions of `key` written at
+    /// or before `seq` -- the newest one that qualifies, if any -- instead of
+    /// always the newest version in the table
+    ///
+    /// A flush can carry several versions of the same key into one SSTable (see
+    /// [`crate::storage::PersistentStore::flush_sealed_memtable`]), so a snapshot
+    /// taken before the flush still needs to see the version it saw rather than
+    /// whatever's newest on disk once [`PersistentStore::get_at`](crate::storage::PersistentStore::get_at)
+    /// falls through to this table. Assumes every version of a given key lands in
+    /// the same data block, which holds as long as one key doesn't accumulate
+    /// enough versions between flushes to overflow a block by itself.
+    pub(crate) fn get_raw_at(&self, key: &[u8], seq: u64) -> Result<Option<Option<Vec<u8>>>> {
+        // The filter was loaded once at `open` (or built by `write_batch`), so a
+        // negative answer here skips the whole disk read below.
+        if !self.filter_policy.may_contain(&self.filter, key) {
             return Ok(None);
         }
 
-        // Read footer
-        let footer_start = file_size - FOOTER_SIZE;
-        file.seek(SeekFrom::Start(footer_start))?;
+        let bytes = self.file_bytes()?;
+        if (bytes.len() as u64) < FOOTER_SIZE {
+            return Ok(None);
+        }
 
-        let mut footer = [0u8; FOOTER_SIZE as usize];
-        file.read_exact(&mut footer)?;
+        let (meta, meta_offset, checksum_tag) = parse_footer_and_meta(&bytes)?;
 
-        // Parse footer
-        let magic_bytes = &footer[0..8];
-        let meta_len = u64::from_le_bytes(footer[8..16].try_into().map_err(|_| {
-            io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Failed to parse metadata length from footer",
-            )
-        })?);
+        // A genuinely empty table (e.g. compaction dropping every tombstone in its
+        // input) has no entries and so no data block to look `key` up in -- that's
+        // a miss, not corruption.
+        if meta.num_entries == 0 {
+            return Ok(None);
+        }
 
-        let meta_offset = u64::from_le_bytes(footer[16..24].try_into().map_err(|_| {
-            io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Failed to parse metadata offset from footer",
-            )
-        })?);
+        // Verify metadata values make sense
+        if meta.data_size == 0 || meta.index_size == 0 {
+            return Err(Error::custom(format!("Invalid metadata values in SSTable: {:?}", meta)));
+        }
 
-        // Verify magic number
-        if magic_bytes != MAGIC_NUMBER.to_be_bytes() {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!(
-                    "Invalid SSTable: magic number mismatch. Got: {:?}, expected: {:?}",
-                    magic_bytes,
-                    MAGIC_NUMBER.to_be_bytes()
-                ),
-            )
-            .into());
-        }
-
-        // Verify metadata offset and length
-        if meta_offset >= footer_start || meta_len == 0 || meta_len > 1024 {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!(
-                    "Invalid metadata in SSTable: offset={}, len={}",
-                    meta_offset, meta_len
-                ),
-            )
-            .into());
-        }
-
-        file.seek(SeekFrom::Start(meta_offset))?;
+        let data_end = meta.data_size;
+        let index_start = data_end;
+        let index_end = index_start + meta.index_size;
 
-        let mut meta_buf = vec![0u8; meta_len as usize];
-        file.read_exact(&mut meta_buf)?;
+        if index_end <= index_start || index_end + meta.filter_size > meta_offset {
+            return Err(Error::custom(format!(
+                "Invalid index section in SSTable: start={}, end={}, meta_offset={}",
+                index_start, index_end, meta_offset
+            )));
+        }
+
+        // Binary-search the index block for the one data block that could hold
+        // `key`: the first whose last key is >= `key`, since blocks are disjoint
+        // and sorted in increasing key order.
+        let codec = self.block_codec();
+        let index_raw = unpack_block(
+            checksum_tag,
+            &bytes[index_start as usize..index_end as usize],
+            index_start,
+            &codec,
+        )?;
+        let index_entries = parse_index_block(&index_raw)?;
+        let block_idx = index_entries.partition_point(|(last_key, _)| last_key.as_slice() < key);
+        let Some((_, handle)) = index_entries.get(block_idx) else {
+            return Ok(None);
+        };
 
-        // Decode the metadata with the same config used for encoding
-        let config = bincode::config::standard()
-            .with_fixed_int_encoding()
-            .with_big_endian();
+        // Each data block is independently packed (compressed, tagged, checksummed)
+        // by `write_batch`, so only the one block `key` could be in needs decoding.
+        if (handle.offset + handle.size) as usize > data_end as usize {
+            return Err(Error::custom("Block handle out of range of the data section"));
+        }
+        let packed = &bytes[handle.offset as usize..(handle.offset + handle.size) as usize];
+        let block = unpack_block(checksum_tag, packed, handle.offset, &codec)?;
+        let (restarts, block_entries) = block_restarts(&block)?;
+        if restarts.is_empty() {
+            return Ok(None);
+        }
 
-        let meta = match bincode::decode_from_slice::<SSTableMeta, _>(&meta_buf, config) {
-            Ok((meta, bytes_read)) => {
-                log::debug!("Successfully decoded metadata, bytes read: {}", bytes_read);
-                meta
+        // Find the restart point to start scanning from (see `restart_index_for_key`
+        // for why this isn't simply "the last restart at or before `key`").
+        let restart_idx = restart_index_for_key(&restarts, block_entries, key)?;
+
+        // Scan forward from the nearest restart, reconstructing keys from their
+        // shared prefix with the previous entry, until `key` is found or passed.
+        // Versions of `key` are written newest-first, so the first one visible at
+        // `seq` is the one to return; an older (or tombstoned) version may still
+        // follow if the newest isn't yet visible.
+        let mut cursor = &block_entries[restarts[restart_idx] as usize..];
+        let mut prev_key = Vec::new();
+        while !cursor.is_empty() {
+            let entry = decode_block_entry(&mut cursor, &prev_key)?;
+            match entry.key.as_slice().cmp(key) {
+                std::cmp::Ordering::Equal if entry.seq <= seq => return Ok(Some(entry.value)),
+                std::cmp::Ordering::Equal => {}
+                std::cmp::Ordering::Greater => return Ok(None),
+                std::cmp::Ordering::Less => {}
             }
-            Err(e) => {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    format!("Failed to decode SSTable metadata: {}", e),
-                )
-                .into());
-            }
-        };
+            prev_key = entry.key;
+        }
 
-        // Verify metadata values make sense
-        if meta.num_entries == 0 || meta.data_size == 0 || meta.index_size == 0 {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!("Invalid metadata values in SSTable: {:?}", meta),
-            )
-            .into());
+        Ok(None)
+    }
+
+    /// Returns the sequence number of the newest version of `key` in this table,
+    /// or `None` if the table holds nothing for it
+    ///
+    /// Used by [`crate::storage::PersistentStore::latest_seq`] to detect whether a
+    /// key has been written since a transaction's snapshot was taken, regardless
+    /// of whether the value looks unchanged (see [`crate::txn`] for why a value
+    /// comparison alone isn't enough). Versions of a key are written newest-first
+    /// (see `# Block Format`), so the first match found is the newest one --
+    /// unlike [`SSTable::get_raw_at`], there's no `seq` ceiling to skip past.
+    pub(crate) fn seq_of(&self, key: &[u8]) -> Result<Option<u64>> {
+        if !self.filter_policy.may_contain(&self.filter, key) {
+            return Ok(None);
+        }
+
+        let bytes = self.file_bytes()?;
+        if (bytes.len() as u64) < FOOTER_SIZE {
+            return Ok(None);
+        }
+
+        let (meta, meta_offset, checksum_tag) = parse_footer_and_meta(&bytes)?;
+        // A genuinely empty table has no entries and so nothing to look `key` up
+        // in -- that's a miss, not corruption.
+        if meta.num_entries == 0 {
+            return Ok(None);
+        }
+        if meta.data_size == 0 || meta.index_size == 0 {
+            return Err(Error::custom(format!("Invalid metadata values in SSTable: {:?}", meta)));
         }
 
         let data_end = meta.data_size;
         let index_start = data_end;
-        let index_end = meta_offset;
-
-        if index_end <= index_start || index_end > meta_offset {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!(
-                    "Invalid index section in SSTable: start={}, end={}, meta_offset={}",
-                    index_start, index_end, meta_offset
-                ),
-            )
-            .into());
-        }
-
-        // Read index entries
-        file.seek(SeekFrom::Start(index_start))?;
-
-        let mut entry_count = 0;
-        let mut _last_key = None;
-
-        while file.stream_position()? < index_end {
-            entry_count += 1;
-
-            // Read key length (8 bytes)
-            let mut key_len_buf = [0u8; 8];
-            file.read_exact(&mut key_len_buf)?;
-            let key_len = u64::from_le_bytes(key_len_buf) as usize;
-
-            // Read key
-            let mut key_buf = vec![0u8; key_len];
-            file.read_exact(&mut key_buf)?;
-
-            // Read value offset
-            let mut offset_buf = [0u8; 8];
-            file.read_exact(&mut offset_buf)?;
-            let value_offset = u64::from_le_bytes(offset_buf);
-
-            let current_key = &key_buf[..];
-            let key_match = current_key == key;
-            _last_key = Some(String::from_utf8_lossy(current_key).to_string());
-
-            // If we found our key, process it immediately
-            if key_match {
-                // Verify the value offset is within the data section
-                if value_offset >= data_end {
-                    return Err(io::Error::new(
-                        io::ErrorKind::InvalidData,
-                        format!(
-                            "Invalid value offset in SSTable: {} >= {}",
-                            value_offset, data_end
-                        ),
-                    )
-                    .into());
-                }
+        let index_end = index_start + meta.index_size;
+        if index_end <= index_start || index_end + meta.filter_size > meta_offset {
+            return Err(Error::custom(format!(
+                "Invalid index section in SSTable: start={}, end={}, meta_offset={}",
+                index_start, index_end, meta_offset
+            )));
+        }
 
-                // Save current position to restore later
-                let _current_pos = file.stream_position()?;
+        let codec = self.block_codec();
+        let index_raw = unpack_block(
+            checksum_tag,
+            &bytes[index_start as usize..index_end as usize],
+            index_start,
+            &codec,
+        )?;
+        let index_entries = parse_index_block(&index_raw)?;
+        let block_idx = index_entries.partition_point(|(last_key, _)| last_key.as_slice() < key);
+        let Some((_, handle)) = index_entries.get(block_idx) else {
+            return Ok(None);
+        };
 
-                // Seek to the value position
-                file.seek(SeekFrom::Start(value_offset))?;
+        if (handle.offset + handle.size) as usize > data_end as usize {
+            return Err(Error::custom("Block handle out of range of the data section"));
+        }
+        let packed = &bytes[handle.offset as usize..(handle.offset + handle.size) as usize];
+        let block = unpack_block(checksum_tag, packed, handle.offset, &codec)?;
+        let (restarts, block_entries) = block_restarts(&block)?;
+        if restarts.is_empty() {
+            return Ok(None);
+        }
 
-                // Read key length (8 bytes)
-                let mut stored_key_len_buf = [0u8; 8];
-                file.read_exact(&mut stored_key_len_buf)?;
-                let stored_key_len = u64::from_le_bytes(stored_key_len_buf) as usize;
+        let restart_idx = restart_index_for_key(&restarts, block_entries, key)?;
 
-                // Read key
-                let mut stored_key_buf = vec![0u8; stored_key_len];
-                file.read_exact(&mut stored_key_buf)?;
+        let mut cursor = &block_entries[restarts[restart_idx] as usize..];
+        let mut prev_key = Vec::new();
+        while !cursor.is_empty() {
+            let entry = decode_block_entry(&mut cursor, &prev_key)?;
+            match entry.key.as_slice().cmp(key) {
+                std::cmp::Ordering::Equal => return Ok(Some(entry.seq)),
+                std::cmp::Ordering::Greater => return Ok(None),
+                std::cmp::Ordering::Less => {}
+            }
+            prev_key = entry.key;
+        }
 
-                // Read value length (8 bytes)
-                let mut value_len_buf = [0u8; 8];
-                file.read_exact(&mut value_len_buf)?;
-                let value_len = u64::from_le_bytes(value_len_buf);
+        Ok(None)
+    }
+
+    /// Returns the current (newest per key) state of the whole table, in sorted key
+    /// order, as `(key, Option<value>)` pairs where `None` marks a tombstone
+    ///
+    /// Equivalent to [`SSTable::range`] over `(Unbounded, Unbounded)`; see there for
+    /// how multiple stored versions of a key are collapsed to just this one.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use rocksdb_clone::storage::SSTable;
+    /// # let sstable = SSTable::open("path/to/sstable").unwrap();
+    /// for (key, value) in sstable.iter().unwrap() {
+    ///     println!("{:?} => {:?}", key, value);
+    /// }
+    /// ```
+    pub fn iter(&self) -> Result<Vec<(Vec<u8>, Option<Vec<u8>>)>> {
+        self.range(Bound::Unbounded, Bound::Unbounded)
+    }
+
+    /// Returns the current (newest per key) state of `[start, end)`, in sorted key
+    /// order, including tombstones so merge logic can see deletions
+    ///
+    /// Equivalent to [`SSTable::range_at`] with `seq = u64::MAX`. Uses the index to
+    /// seek straight to the first block that could hold a key in range instead of
+    /// decoding the table from the start, so a narrow range against a large table
+    /// only pays for the blocks it actually overlaps.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use rocksdb_clone::storage::SSTable;
+    /// # use std::ops::Bound;
+    /// # let sstable = SSTable::open("path/to/sstable").unwrap();
+    /// for (key, value) in sstable.range(Bound::Included(b"a"), Bound::Excluded(b"m")).unwrap() {
+    ///     println!("{:?} => {:?}", key, value);
+    /// }
+    /// ```
+    pub fn range(&self, start: Bound<&[u8]>, end: Bound<&[u8]>) -> Result<Vec<(Vec<u8>, Option<Vec<u8>>)>> {
+        self.range_at(start, end, u64::MAX)
+    }
+
+    /// Like [`SSTable::range`], but collapses each key down to the newest version
+    /// visible at `seq` instead of always the table's current (newest) version
+    ///
+    /// A flush can carry several versions of the same key into one SSTable (see
+    /// [`crate::storage::PersistentStore::flush_sealed_memtable`]); this is what
+    /// lets [`PersistentStore::scan`](crate::storage::PersistentStore::scan) read a
+    /// consistent point-in-time view straight out of such a table, the same way
+    /// [`crate::storage::MemTable::iter_at`] does for the MemTable.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use rocksdb_clone::storage::SSTable;
+    /// # use std::ops::Bound;
+    /// # let sstable = SSTable::open("path/to/sstable").unwrap();
+    /// for (key, value) in sstable.range_at(Bound::Included(b"a"), Bound::Excluded(b"m"), 10).unwrap() {
+    ///     println!("{:?} => {:?}", key, value);
+    /// }
+    /// ```
+    pub fn range_at(
+        &self,
+        start: Bound<&[u8]>,
+        end: Bound<&[u8]>,
+        seq: u64,
+    ) -> Result<Vec<(Vec<u8>, Option<Vec<u8>>)>> {
+        let bytes = self.file_bytes()?;
+        if (bytes.len() as u64) < FOOTER_SIZE {
+            return Ok(Vec::new());
+        }
 
-                if value_len == u64::MAX {
-                    return Ok(None);
+        let (meta, _, checksum_tag) = parse_footer_and_meta(&bytes)?;
+        if meta.num_entries == 0 {
+            return Ok(Vec::new());
+        }
+
+        let codec = self.block_codec();
+        let index_start = meta.data_size as usize;
+        let index_end = index_start + meta.index_size as usize;
+        let index_raw = unpack_block(checksum_tag, &bytes[index_start..index_end], index_start as u64, &codec)?;
+        let index_entries = parse_index_block(&index_raw)?;
+
+        // Find the first block whose last key could be in range; every earlier
+        // block's last (and therefore every) key sorts before `start`.
+        let first_block = match start {
+            Bound::Included(s) => index_entries.partition_point(|(last_key, _)| last_key.as_slice() < s),
+            Bound::Excluded(s) => index_entries.partition_point(|(last_key, _)| last_key.as_slice() <= s),
+            Bound::Unbounded => 0,
+        };
+
+        let mut entries = Vec::new();
+        for (_, handle) in &index_entries[first_block..] {
+            let packed = &bytes[handle.offset as usize..(handle.offset + handle.size) as usize];
+            let block = unpack_block(checksum_tag, packed, handle.offset, &codec)?;
+            let (_, block_entries) = block_restarts(&block)?;
+
+            let mut cursor = block_entries;
+            let mut prev_key = Vec::new();
+            let mut past_end = false;
+            // Tracks the key whose visible version has already been resolved
+            // (emitted or skipped), so older versions of the same key -- which
+            // are written right after it, see `# Block Format` -- aren't
+            // considered again.
+            let mut resolved_key: Option<Vec<u8>> = None;
+            while !cursor.is_empty() {
+                let entry = decode_block_entry(&mut cursor, &prev_key)?;
+                prev_key = entry.key.clone();
+
+                let before_end = match end {
+                    Bound::Included(e) => entry.key.as_slice() <= e,
+                    Bound::Excluded(e) => entry.key.as_slice() < e,
+                    Bound::Unbounded => true,
+                };
+                if !before_end {
+                    past_end = true;
+                    break;
                 }
 
-                // Read the value in chunks to avoid large allocations
-                let mut value = Vec::with_capacity(value_len as usize);
-                let mut remaining = value_len;
-                let mut buf = [0u8; 8192]; // 8KB buffer
-
-                while remaining > 0 {
-                    let to_read = std::cmp::min(remaining, buf.len() as u64) as usize;
-                    let buf = &mut buf[..to_read];
-                    file.read_exact(buf)?;
-                    value.extend_from_slice(buf);
-                    remaining -= to_read as u64;
+                if entry.seq > seq {
+                    continue;
                 }
+                if resolved_key.as_deref() == Some(entry.key.as_slice()) {
+                    continue;
+                }
+                resolved_key = Some(entry.key.clone());
+
+                let after_start = match start {
+                    Bound::Included(s) => entry.key.as_slice() >= s,
+                    Bound::Excluded(s) => entry.key.as_slice() > s,
+                    Bound::Unbounded => true,
+                };
+                if after_start {
+                    entries.push((entry.key, entry.value));
+                }
+            }
 
-                return Ok(Some(value));
+            if past_end {
+                break;
             }
         }
 
-        Ok(None)
+        Ok(entries)
+    }
+
+    /// Returns every version of every key in this table, including tombstones,
+    /// in the same order they're stored in: ascending by key, then descending
+    /// by sequence number
+    ///
+    /// Unlike [`SSTable::iter`]/[`SSTable::range_at`], this doesn't collapse a
+    /// key down to the single version visible at some sequence number -- it's
+    /// used by compaction so merging tables together doesn't throw away a
+    /// version a live [`crate::Snapshot`](crate::Snapshot) taken before the
+    /// compaction might still need.
+    pub(crate) fn iter_all_versions(&self) -> Result<Vec<(Vec<u8>, u64, Option<Vec<u8>>)>> {
+        let bytes = self.file_bytes()?;
+        if (bytes.len() as u64) < FOOTER_SIZE {
+            return Ok(Vec::new());
+        }
+
+        let (meta, _, checksum_tag) = parse_footer_and_meta(&bytes)?;
+        if meta.num_entries == 0 {
+            return Ok(Vec::new());
+        }
+
+        let codec = self.block_codec();
+        let index_start = meta.data_size as usize;
+        let index_end = index_start + meta.index_size as usize;
+        let index_raw = unpack_block(checksum_tag, &bytes[index_start..index_end], index_start as u64, &codec)?;
+        let index_entries = parse_index_block(&index_raw)?;
+
+        let mut entries = Vec::new();
+        for (_, handle) in &index_entries {
+            let packed = &bytes[handle.offset as usize..(handle.offset + handle.size) as usize];
+            let block = unpack_block(checksum_tag, packed, handle.offset, &codec)?;
+            let (_, block_entries) = block_restarts(&block)?;
+
+            let mut cursor = block_entries;
+            let mut prev_key = Vec::new();
+            while !cursor.is_empty() {
+                let entry = decode_block_entry(&mut cursor, &prev_key)?;
+                prev_key = entry.key.clone();
+                entries.push((entry.key, entry.seq, entry.value));
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Dumps the newest version of every key in this table, including tombstones,
+    /// as human-readable JSON lines to `writer`: a header line with the table's
+    /// metadata, followed by one line per entry with its key and value hex-encoded
+    /// for binary safety
+    ///
+    /// Pairs with [`SSTable::restore`] to inspect, diff, or rebuild a table without
+    /// a running database. Entries are written in the same sorted order
+    /// [`SSTable::iter`] returns them in; older versions of a key aren't written.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use rocksdb_clone::storage::SSTable;
+    /// # let sstable = SSTable::open("path/to/sstable").unwrap();
+    /// let mut out = Vec::new();
+    /// sstable.dump(&mut out).unwrap();
+    /// ```
+    pub fn dump(&self, mut writer: impl Write) -> Result<()> {
+        let entries = self.iter()?;
+
+        let header = DumpHeader {
+            num_entries: entries.len() as u64,
+            compression: self.compression,
+        };
+        serde_json::to_writer(&mut writer, &header)?;
+        writer.write_all(b"\n")?;
+
+        for (key, value) in entries {
+            let entry = DumpEntry {
+                key: hex_encode(&key),
+                value: value.as_deref().map(hex_encode),
+            };
+            serde_json::to_writer(&mut writer, &entry)?;
+            writer.write_all(b"\n")?;
+        }
+
+        Ok(())
+    }
+
+    /// Rebuilds an SSTable at `path` from [`SSTable::dump`]'s JSON-lines format
+    ///
+    /// The restored table reuses the compression codec recorded in the dump's
+    /// header, but never encryption: a dump is plaintext by design, so restoring
+    /// one always produces an unencrypted table, even if the original was
+    /// encrypted.
+    ///
+    /// # Errors
+    /// Returns an error if `reader`'s first line isn't a valid header, any entry
+    /// line fails to parse, or a key isn't strictly greater than the one before it
+    /// (the same invariant [`SSTableBuilder`] enforces).
+    pub fn restore(path: impl AsRef<Path>, reader: impl BufRead) -> Result<Self> {
+        let mut lines = reader.lines();
+
+        let header_line = lines
+            .next()
+            .ok_or_else(|| Error::custom("Dump is empty; expected a header line"))??;
+        let header: DumpHeader = serde_json::from_str(&header_line)?;
+
+        let mut builder = SSTableBuilder::new(path, header.compression, DEFAULT_BITS_PER_KEY, false)?;
+        for line in lines {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+
+            let entry: DumpEntry = serde_json::from_str(&line)?;
+            let key = hex_decode(&entry.key)?;
+            // A dump (see `SSTable::dump`) only ever holds one version per key,
+            // so there's no prior write for the sequence number to distinguish
+            // from -- `0` is as good as any other value here.
+            match entry.value {
+                Some(value) => builder.add(&key, 0, &hex_decode(&value)?)?,
+                None => builder.add_tombstone(&key, 0)?,
+            }
+        }
+
+        builder.finish()
+    }
+}
+
+/// Header line written by [`SSTable::dump`], ahead of the table's entries
+#[derive(Debug, Serialize, Deserialize)]
+struct DumpHeader {
+    /// Number of entries (including tombstones) that follow the header
+    num_entries: u64,
+    /// Compression codec the dumped table was configured with; reused by
+    /// [`SSTable::restore`] so the rebuilt table keeps the same codec
+    compression: Compression,
+}
+
+/// One entry line written by [`SSTable::dump`]
+#[derive(Debug, Serialize, Deserialize)]
+struct DumpEntry {
+    /// The entry's key, hex-encoded
+    key: String,
+    /// The entry's value hex-encoded, or `None` for a tombstone
+    value: Option<String>,
+}
+
+/// Encodes `bytes` as a lowercase hex string, for [`SSTable::dump`]
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Reverses [`hex_encode`], for [`SSTable::restore`]
+fn hex_decode(s: &str) -> Result<Vec<u8>> {
+    if s.len() % 2 != 0 {
+        return Err(Error::custom(format!("hex string has an odd length: {:?}", s)));
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&s[i..i + 2], 16)
+                .map_err(|e| Error::custom(format!("invalid hex byte {:?}: {}", &s[i..i + 2], e)))
+        })
+        .collect()
+}
+
+/// Streams entries into a new SSTable file incrementally, instead of requiring the
+/// whole key-value set materialized up front like [`SSTable::write_batch`] does
+///
+/// Built for compaction, where merging several tables' inputs into one already
+/// yields entries one at a time (see
+/// [`crate::storage::merge::VersionMergeIterator`]); collecting them into a `Vec`
+/// first just to hand them to `write_batch` would double the peak memory a large
+/// compaction needs. Keys must be added in non-decreasing order, with repeats
+/// (another version of the same key) requiring a strictly decreasing sequence
+/// number -- the same invariant `write_batch`'s caller has always had to uphold,
+/// but enforced here as entries are streamed in rather than surfacing as a
+/// garbled read much later.
+///
+/// # Example
+/// ```no_run
+/// use rocksdb_clone::storage::SSTableBuilder;
+///
+/// let mut builder = SSTableBuilder::new("path/to/sstable", Default::default(), 10, false).unwrap();
+/// builder.add(b"key1", 1, b"value1").unwrap();
+/// builder.add_tombstone(b"key2", 2).unwrap();
+/// let sstable = builder.finish().unwrap();
+/// ```
+pub struct SSTableBuilder {
+    writer: BufWriter<File>,
+    path: PathBuf,
+    compression: Compression,
+    registry: CompressorRegistry,
+    filter_policy: Arc<dyn FilterPolicy>,
+    use_mmap: bool,
+    encryption: EncryptionType,
+    key: Option<[u8; encryption::KEY_LEN]>,
+    salt: [u8; encryption::SALT_LEN],
+    encryption_registry: EncryptionRegistry,
+
+    block: BlockBuilder,
+    index_entries: Vec<(Vec<u8>, BlockHandle)>,
+    keys: Vec<Vec<u8>>,
+    data_len: u64,
+    num_entries: u64,
+    last_key: Option<Vec<u8>>,
+    /// Sequence number `last_key` was last added with, so a repeated key (another
+    /// version of the same key) can be checked for strictly decreasing `seq`
+    last_seq: u64,
+}
+
+impl SSTableBuilder {
+    /// Creates a new streaming builder at `path`, equivalent in its settings to
+    /// [`SSTable::create_with_options`] but accepting entries one at a time via
+    /// [`SSTableBuilder::add`] / [`SSTableBuilder::add_tombstone`] instead of a
+    /// single in-memory batch
+    pub fn new(path: impl AsRef<Path>, compression: Compression, bits_per_key: usize, use_mmap: bool) -> Result<Self> {
+        Self::new_with_encryption(path, compression, bits_per_key, use_mmap, EncryptionType::None, None)
+    }
+
+    /// Creates a new streaming builder at `path`, additionally sealing its data and
+    /// index blocks with `encryption`, equivalent in its settings to
+    /// [`SSTable::create_with_encryption`]
+    pub fn new_with_encryption(
+        path: impl AsRef<Path>,
+        compression: Compression,
+        bits_per_key: usize,
+        use_mmap: bool,
+        encryption: EncryptionType,
+        passphrase: Option<&[u8]>,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        if path.exists() {
+            return Err(Error::custom("File already exists"));
+        }
+
+        let (salt, key) = if encryption == EncryptionType::None {
+            ([0u8; encryption::SALT_LEN], None)
+        } else {
+            let passphrase = passphrase
+                .ok_or_else(|| Error::InvalidArgument("a passphrase is required to create an encrypted SSTable".into()))?;
+            let salt = encryption::generate_salt();
+            (salt, Some(encryption::derive_key(passphrase, &salt)?))
+        };
+
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .read(true)
+            .write(true)
+            .open(path)?;
+
+        Ok(Self {
+            writer: BufWriter::new(file),
+            path: path.to_path_buf(),
+            compression,
+            registry: CompressorRegistry::default(),
+            filter_policy: bloom::policy_for(bits_per_key),
+            use_mmap,
+            encryption,
+            key,
+            salt,
+            encryption_registry: EncryptionRegistry::default(),
+            block: BlockBuilder::default(),
+            index_entries: Vec::new(),
+            keys: Vec::new(),
+            data_len: 0,
+            num_entries: 0,
+            last_key: None,
+            last_seq: 0,
+        })
+    }
+
+    /// Bundles this builder's compression and encryption settings into a
+    /// [`BlockCodec`] for [`pack_block`]
+    fn block_codec(&self) -> BlockCodec<'_> {
+        BlockCodec {
+            compression: self.compression,
+            compression_registry: &self.registry,
+            encryption: self.encryption,
+            encryption_registry: &self.encryption_registry,
+            key: self.key.as_ref(),
+        }
+    }
+
+    /// Adds a key-value pair at `seq`
+    ///
+    /// # Errors
+    /// Returns an error if `key` isn't at least as large as the last key added,
+    /// or if `key` repeats the last key added without `seq` being strictly
+    /// smaller than the sequence number it was added with.
+    pub fn add(&mut self, key: &[u8], seq: u64, value: &[u8]) -> Result<()> {
+        self.add_entry(key, seq, Some(value))
+    }
+
+    /// Adds a tombstone (deletion marker) for `key` at `seq`
+    ///
+    /// # Errors
+    /// See [`SSTableBuilder::add`].
+    pub fn add_tombstone(&mut self, key: &[u8], seq: u64) -> Result<()> {
+        self.add_entry(key, seq, None)
+    }
+
+    fn add_entry(&mut self, key: &[u8], seq: u64, value: Option<&[u8]>) -> Result<()> {
+        if let Some(last_key) = &self.last_key {
+            match key.cmp(last_key.as_slice()) {
+                std::cmp::Ordering::Less => {
+                    return Err(Error::InvalidArgument(format!(
+                        "SSTableBuilder requires non-decreasing keys: {:?} was added after {:?}",
+                        key, last_key
+                    )));
+                }
+                // A repeated key is another version of it, which has to be
+                // strictly older than the one just added -- entries within a key
+                // are stored newest-first (see `# Block Format`), and a caller
+                // reading this back expects that order to hold.
+                std::cmp::Ordering::Equal if seq >= self.last_seq => {
+                    return Err(Error::InvalidArgument(format!(
+                        "SSTableBuilder requires a repeated key's seq to strictly decrease: {} was added after {} for key {:?}",
+                        seq, self.last_seq, key
+                    )));
+                }
+                _ => {}
+            }
+        }
+
+        self.block.add(key, seq, value);
+        self.keys.push(key.to_vec());
+        self.num_entries += 1;
+        self.last_key = Some(key.to_vec());
+        self.last_seq = seq;
+
+        if self.block.buf.len() >= BLOCK_SIZE_TARGET {
+            let last_key = key.to_vec();
+            self.flush_block(last_key)?;
+        }
+
+        Ok(())
+    }
+
+    /// Packs and writes out the current block, recording it in the index under `last_key`
+    fn flush_block(&mut self, last_key: Vec<u8>) -> Result<()> {
+        let raw = std::mem::take(&mut self.block).finish();
+        let packed = pack_block(&raw, &self.block_codec())?;
+        let offset = self.data_len;
+        let size = packed.len() as u64;
+        self.writer.write_all(&packed)?;
+        self.data_len += size;
+        self.index_entries.push((last_key, BlockHandle { offset, size }));
+        Ok(())
+    }
+
+    /// Flushes any partial block, builds and writes the index, filter, metadata,
+    /// and footer, and returns the now-readable `SSTable`
+    pub fn finish(mut self) -> Result<SSTable> {
+        if !self.block.is_empty() {
+            // Safe to unwrap: a non-empty block means at least one entry was added,
+            // so `last_key` has been set.
+            let last_key = self.last_key.clone().unwrap();
+            self.flush_block(last_key)?;
+        }
+
+        let key_refs: Vec<&[u8]> = self.keys.iter().map(Vec::as_slice).collect();
+        let filter = self.filter_policy.build(&key_refs);
+
+        let index_start = self.data_len;
+        let mut index_raw = Vec::new();
+        for (last_key, handle) in &self.index_entries {
+            index_raw.extend_from_slice(&(last_key.len() as u64).to_le_bytes());
+            index_raw.extend_from_slice(last_key);
+            index_raw.extend_from_slice(&handle.offset.to_le_bytes());
+            index_raw.extend_from_slice(&handle.size.to_le_bytes());
+        }
+        // Packed uncompressed (see `index_codec`): the index is read on every
+        // lookup, so paying decompression cost there would undercut the whole
+        // point of seeking via it instead of scanning the data section.
+        let index_buf = pack_block(&index_raw, &index_codec(&self.block_codec()))?;
+        self.writer.write_all(&index_buf)?;
+        let index_size = index_buf.len() as u64;
+
+        self.writer.write_all(&filter)?;
+        let filter_size = filter.len() as u64;
+
+        let min_key = self.keys.first().cloned().unwrap_or_default();
+        let max_key = self.keys.last().cloned().unwrap_or_default();
+        write_footer(
+            &mut self.writer,
+            self.num_entries,
+            index_start,
+            index_size,
+            filter_size,
+            self.compression.tag(),
+            self.encryption.tag(),
+            self.salt,
+            min_key,
+            max_key,
+            CURRENT_FORMAT_VERSION,
+        )?;
+        self.writer.flush()?;
+
+        let file = self
+            .writer
+            .into_inner()
+            .map_err(|e| Error::custom(format!("Failed to flush SSTable writer: {}", e)))?;
+
+        let mut sstable = SSTable {
+            file,
+            path: self.path,
+            compression: self.compression,
+            registry: self.registry,
+            filter_policy: self.filter_policy,
+            filter,
+            use_mmap: self.use_mmap,
+            mmap: None,
+            encryption: self.encryption,
+            key: self.key,
+            salt: self.salt,
+            encryption_registry: self.encryption_registry,
+        };
+        sstable.refresh_mmap();
+
+        Ok(sstable)
     }
 }
@@ -0,0 +1,104 @@
+//! Optimistic transactions with write-snapshot isolation
+//!
+//! A [`Transaction`] reads against a [`Snapshot`] fixed when it begins and buffers its
+//! writes (like a [`WriteBatch`]) instead of applying them immediately. Committing it
+//! checks that every key it read has no write newer than that snapshot; if any of them
+//! were written again in the meantime, the whole transaction is aborted with
+//! [`crate::error::Error::TransactionConflict`] rather than applying writes based on data
+//! that's no longer current. This is write-snapshot isolation: every schedule it commits
+//! really is serializable.
+//!
+//! The conflict check compares sequence numbers, not values: a key that was written back
+//! to the exact value the transaction read is still a conflict, since some other write
+//! committed against it in the meantime and this transaction's decision to commit was
+//! made without seeing that write. Comparing values instead would miss exactly this case
+//! (the ABA problem) and let a schedule through that isn't actually serializable.
+
+use crate::batch::WriteBatch;
+use crate::error::Result;
+use crate::{Snapshot, DB};
+
+/// A buffered, optimistically-isolated transaction; see the [module docs](self) for
+/// the conflict model
+///
+/// Reads and writes are both deferred: reads are served from `db` as of this
+/// transaction's snapshot and recorded into its read set, and writes are buffered in a
+/// [`WriteBatch`] exactly like [`DB::write`] -- neither takes effect until
+/// [`DB::commit_transaction`] validates the read set and applies the writes together.
+pub struct Transaction {
+    snapshot: Snapshot,
+    reads: Vec<Vec<u8>>,
+    writes: WriteBatch,
+}
+
+impl Transaction {
+    /// Begins a new transaction reading against `snapshot`
+    pub(crate) fn new(snapshot: Snapshot) -> Self {
+        Self {
+            snapshot,
+            reads: Vec::new(),
+            writes: WriteBatch::new(),
+        }
+    }
+
+    /// Returns the snapshot this transaction reads against
+    pub fn snapshot(&self) -> Snapshot {
+        self.snapshot.clone()
+    }
+
+    /// Reads `key` as of this transaction's snapshot, recording it in the read set
+    ///
+    /// If `key` has been written again by the time this transaction tries to commit,
+    /// the commit aborts -- this is how the transaction notices it read data that's
+    /// since become stale.
+    pub fn get(&mut self, db: &DB, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let value = db.get_at(key, &self.snapshot)?;
+        self.reads.push(key.to_vec());
+        Ok(value)
+    }
+
+    /// Buffers a put, applied only if this transaction commits
+    ///
+    /// # Errors
+    /// Returns [`crate::error::Error::WriteBatchFull`] if the underlying write batch
+    /// is already at its maximum capacity.
+    pub fn put(&mut self, key: impl Into<Vec<u8>>, value: impl Into<Vec<u8>>) -> Result<()> {
+        self.writes.put(key, value)
+    }
+
+    /// Buffers a delete, applied only if this transaction commits
+    ///
+    /// # Errors
+    /// Returns [`crate::error::Error::WriteBatchFull`] if the underlying write batch
+    /// is already at its maximum capacity.
+    pub fn delete(&mut self, key: impl Into<Vec<u8>>) -> Result<()> {
+        self.writes.delete(key)
+    }
+
+    /// Returns the keys read so far, for [`DB::commit_transaction`] to check against
+    /// the current database for a write newer than this transaction's snapshot
+    pub(crate) fn reads(&self) -> &[Vec<u8>] {
+        &self.reads
+    }
+
+    /// Consumes this transaction, returning its buffered writes for
+    /// [`DB::commit_transaction`] to apply
+    pub(crate) fn into_writes(self) -> WriteBatch {
+        self.writes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transaction_buffers_writes_like_a_write_batch() {
+        let mut txn = Transaction::new(Snapshot { seq: 0, pin: None });
+        txn.put(b"a".to_vec(), b"1".to_vec()).unwrap();
+        txn.delete(b"b".to_vec()).unwrap();
+
+        let writes = txn.into_writes();
+        assert_eq!(writes.len(), 2);
+    }
+}